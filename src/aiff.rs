@@ -0,0 +1,706 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::dither::{DitherConfig, Quantizer};
+use crate::error::{Error, Result};
+use crate::g711::Law;
+use crate::normalize::{Normalize, SampleFormat};
+use crate::read::{check_magic, decode_samples, deinterleave};
+use crate::riff::Chunk;
+use crate::spec::WaveSpec;
+use crate::write::{encode_samples, encode_samples_dithered, interleave};
+
+/// Big-endian unsigned integer of 2 bytes at `at`.
+fn be_u16_at(bytes: &[u8], at: usize) -> usize {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]]) as usize
+}
+
+/// Big-endian unsigned integer of 4 bytes at `at`.
+fn be_u32_at(bytes: &[u8], at: usize) -> usize {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize
+}
+
+/// Highest sample rate, which fits in the 32-bit field of `fmt ` chunk of the output.
+const MAX_SAMPLE_RATE: f64 = u32::MAX as f64;
+
+/// 80-bit IEEE 754 extended precision number, which is the sample rate of `COMM` chunk.
+pub fn extended_to_f64(bytes: [u8; 10]) -> f64 {
+    let exponent = ((bytes[0] as i32 & 0x7F) << 8) | bytes[1] as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    // the mantissa has the explicit integer bit
+    let value = mantissa as f64 * 2.0_f64.powi(exponent - 16383 - 63);
+    if bytes[0] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Normal or zero `value` as 80-bit IEEE 754 extended precision number.
+pub fn f64_to_extended(value: f64) -> [u8; 10] {
+    let mut bytes = [0; 10];
+    if value == 0.0 || !value.is_normal() {
+        return bytes;
+    }
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7FF) as i32 - 1023 + 16383;
+    let mantissa = ((bits & ((1 << 52) - 1)) | (1 << 52)) << 11;
+    let sign = if value < 0.0 { 0x8000 } else { 0 };
+    bytes[0..2].copy_from_slice(&((sign | exponent) as u16).to_be_bytes());
+    bytes[2..10].copy_from_slice(&mantissa.to_be_bytes());
+
+    bytes
+}
+
+/// Reverse the byte order of each sample of `width` bytes.
+pub(crate) fn swap_bytes(bytes: &mut [u8], width: usize) {
+    for sample in bytes.chunks_exact_mut(width) {
+        sample.reverse();
+    }
+}
+
+/// Signed 8-bit samples of AIFF and unsigned ones of WAVE differ in the sign bit.
+fn flip_sign_bits(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        *byte ^= 0x80;
+    }
+}
+
+/// How samples of `SSND` chunk are encoded, which is given by the compression type of AIFF-C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiffEncoding {
+    /// big-endian signed integers, AIFF or `NONE` and `twos` of AIFF-C
+    BigEndian,
+    /// little-endian signed integers, `sowt`
+    LittleEndian,
+    /// unsigned 8-bit integers, `raw `
+    Unsigned,
+    /// big-endian IEEE float, `fl32` and `fl64`
+    Float,
+    /// G.711 A-law or mu-law, `alaw` and `ulaw`
+    G711(Law),
+}
+
+impl AiffEncoding {
+    pub fn from_compression_type(compression_type: &[u8]) -> Option<Self> {
+        match compression_type {
+            b"NONE" | b"twos" => Some(AiffEncoding::BigEndian),
+            b"sowt" => Some(AiffEncoding::LittleEndian),
+            b"raw " => Some(AiffEncoding::Unsigned),
+            b"fl32" | b"FL32" | b"fl64" | b"FL64" => Some(AiffEncoding::Float),
+            b"alaw" | b"ALAW" => Some(AiffEncoding::G711(Law::ALaw)),
+            b"ulaw" | b"ULAW" => Some(AiffEncoding::G711(Law::MuLaw)),
+            _ => None,
+        }
+    }
+}
+
+/// Fields of `COMM` chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiffFormat {
+    pub number_of_channels: usize,
+    pub sample_frames: usize,
+    /// significant bits of a sample, which is left-justified in whole bytes
+    pub bits_per_sample: usize,
+    pub sample_rate: f64,
+    pub encoding: AiffEncoding,
+}
+
+impl AiffFormat {
+    /// Parse `COMM` chunk data, which has a compression type in AIFF-C.
+    pub fn parse(comm: &[u8], aifc: bool, offset_of_chunk_header: usize) -> Result<Self> {
+        let size = if aifc { 22 } else { 18 };
+        if comm.len() < size {
+            return Err(Error::IncompleteFormatChunk {
+                offset: offset_of_chunk_header,
+                format: if aifc { "AIFF-C" } else { "AIFF" }.to_string(),
+            });
+        }
+        let sample_size = be_u16_at(comm, 6);
+        let encoding = if aifc {
+            AiffEncoding::from_compression_type(&comm[18..22]).ok_or_else(|| {
+                Error::UnsupportedCompressionType {
+                    offset: offset_of_chunk_header + 8 + 18,
+                    name: String::from_utf8_lossy(&comm[18..22]).to_string(),
+                }
+            })?
+        } else {
+            AiffEncoding::BigEndian
+        };
+        let bits_per_sample = match (encoding, &comm.get(18..22)) {
+            // sample size of float is not reliable
+            (AiffEncoding::Float, Some(b"fl64" | b"FL64")) => 64,
+            (AiffEncoding::Float, _) => 32,
+            (AiffEncoding::G711(_), _) => 8,
+            _ => sample_size,
+        };
+
+        let format = AiffFormat {
+            number_of_channels: be_u16_at(comm, 0),
+            sample_frames: be_u32_at(comm, 2),
+            bits_per_sample,
+            sample_rate: extended_to_f64(comm[8..18].try_into().unwrap()),
+            encoding,
+        };
+        if format.number_of_channels == 0 {
+            return Err(Error::InvalidChannels(format.number_of_channels));
+        }
+        // NaN and infinity fail too, and rates below 0.5 Hz round to 0
+        if !(0.5..=MAX_SAMPLE_RATE).contains(&format.sample_rate) {
+            return Err(Error::UnsupportedSampleRate(
+                format.sample_rate.round() as usize,
+                "AIFF",
+            ));
+        }
+        if !(1..=32).contains(&format.bits_per_sample) && format.encoding != AiffEncoding::Float {
+            return Err(Error::UnsupportedBitsPerSample(
+                format.bits_per_sample,
+                SampleFormat::Int,
+            ));
+        }
+
+        Ok(format)
+    }
+
+    /// Bytes of a sample.
+    pub fn sample_width(&self) -> usize {
+        self.bits_per_sample.div_ceil(8)
+    }
+
+    /// Bytes of a frame.
+    pub fn block_align(&self) -> usize {
+        self.number_of_channels * self.sample_width()
+    }
+
+    /// Format of linear PCM or IEEE float which decoded samples fit in.
+    pub fn spec(&self) -> WaveSpec {
+        let (sample_format, bits_per_sample) = match self.encoding {
+            AiffEncoding::Float => (SampleFormat::Float, self.bits_per_sample),
+            AiffEncoding::G711(_) => (SampleFormat::Int, 16),
+            _ => (SampleFormat::Int, self.sample_width() * 8),
+        };
+
+        WaveSpec {
+            channels: self.number_of_channels,
+            sample_rate: self.sample_rate.round() as usize,
+            sample_format,
+            bits_per_sample,
+        }
+    }
+
+    /// Decode whole frames into interleaved samples in the range from -1.0 to 1.0.
+    pub fn decode(&self, raw_chunk_data: &[u8]) -> Result<Vec<f64>> {
+        let width = self.sample_width();
+        let mut bytes = raw_chunk_data.to_vec();
+        match self.encoding {
+            AiffEncoding::G711(law) => {
+                return Ok(bytes
+                    .iter()
+                    .map(|a| law.decode(*a).to_normalized())
+                    .collect())
+            }
+            AiffEncoding::BigEndian | AiffEncoding::Float => swap_bytes(&mut bytes, width),
+            AiffEncoding::LittleEndian | AiffEncoding::Unsigned => {}
+        }
+        if width == 1 && self.encoding != AiffEncoding::Unsigned {
+            flip_sign_bits(&mut bytes);
+        }
+        let spec = self.spec();
+
+        decode_samples(&bytes, spec.sample_format, spec.bits_per_sample)
+    }
+}
+
+/// Reader of AIFF and AIFF-C which decodes samples as they are needed.
+#[derive(Debug)]
+pub struct AiffReader<R> {
+    reader: R,
+    format: AiffFormat,
+    /// samples of `SSND` chunk following its offset and block size
+    sound_data: Chunk,
+    /// bytes of samples which are not read yet
+    remaining: usize,
+}
+
+impl<R: Read + Seek> AiffReader<R> {
+    /// Parse the header, the source is left at the beginning of samples.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let file_size = reader.seek(SeekFrom::End(0))? as usize;
+        reader.seek(SeekFrom::Start(0))?;
+
+        // FORM header
+        let mut header = [0; 12];
+        if file_size < header.len() {
+            return Err(Error::TruncatedHeader {
+                name: "FORM header",
+                offset: 0,
+                size: header.len(),
+                file_size,
+            });
+        }
+        reader.read_exact(&mut header)?;
+        check_magic(&header, 0, "FORM")?;
+        let aifc = &header[8..12] == b"AIFC";
+        if !aifc {
+            check_magic(&header, 8, "AIFF")?;
+        }
+
+        // Walk chunks with their big-endian sizes, only `COMM` and `SSND` chunk are read.
+        let mut format = None;
+        let mut sound_data = None;
+        let mut position = header.len();
+        while position + 8 <= file_size {
+            let mut chunk_header = [0; 8];
+            reader.seek(SeekFrom::Start(position as u64))?;
+            reader.read_exact(&mut chunk_header)?;
+            let chunk = Chunk {
+                id: [
+                    chunk_header[0],
+                    chunk_header[1],
+                    chunk_header[2],
+                    chunk_header[3],
+                ],
+                offset: position + 8,
+                size: be_u32_at(&chunk_header, 4),
+            };
+            let available = chunk.size.min(file_size - chunk.offset);
+            match &chunk.id {
+                b"COMM" if format.is_none() => {
+                    let mut comm = vec![0; available];
+                    reader.read_exact(&mut comm)?;
+                    format = Some(AiffFormat::parse(&comm, aifc, position)?);
+                }
+                b"SSND" if sound_data.is_none() => {
+                    if available < 8 {
+                        return Err(Error::TruncatedHeader {
+                            name: "SSND chunk",
+                            offset: position,
+                            size: 16,
+                            file_size,
+                        });
+                    }
+                    let mut fields = [0; 8];
+                    reader.read_exact(&mut fields)?;
+                    // samples begin after the offset
+                    let offset = be_u32_at(&fields, 0).min(chunk.size - 8);
+                    sound_data = Some(Chunk {
+                        id: chunk.id,
+                        offset: chunk.offset + 8 + offset,
+                        size: chunk.size - 8 - offset,
+                    });
+                }
+                _ => {}
+            }
+            position = chunk.offset + chunk.padded_size();
+        }
+        let format = format.ok_or(Error::MissingChunk("COMM"))?;
+        let sound_data = sound_data.ok_or(Error::MissingChunk("SSND"))?;
+        if sound_data.offset + sound_data.size > file_size {
+            return Err(Error::DataExceedsFile {
+                offset: sound_data.offset,
+                size: sound_data.size,
+                file_size,
+            });
+        }
+        reader.seek(SeekFrom::Start(sound_data.offset as u64))?;
+        // samples after the number of sample frames are ignored
+        let remaining = sound_data
+            .size
+            .min(format.sample_frames * format.block_align());
+
+        Ok(AiffReader {
+            reader,
+            format,
+            sound_data,
+            remaining,
+        })
+    }
+
+    pub fn format(&self) -> &AiffFormat {
+        &self.format
+    }
+
+    pub fn spec(&self) -> WaveSpec {
+        self.format.spec()
+    }
+
+    /// Samples of `SSND` chunk.
+    pub fn sound_data(&self) -> &Chunk {
+        &self.sound_data
+    }
+
+    /// Number of frames of the whole sound data.
+    pub fn duration(&self) -> usize {
+        self.format
+            .sample_frames
+            .min(self.sound_data.size / self.format.block_align().max(1))
+    }
+
+    /// Read at most `frames` frames as samples of each channel, they are empty at the end.
+    pub fn read_frames(&mut self, frames: usize) -> Result<Vec<Vec<f64>>> {
+        let block_align = self.format.block_align().max(1);
+        let size = (frames * block_align).min(self.remaining / block_align * block_align);
+        let mut raw_chunk_data = vec![0; size];
+        self.reader.read_exact(&mut raw_chunk_data)?;
+        self.remaining -= size;
+
+        let samples = self.format.decode(&raw_chunk_data)?;
+
+        Ok(deinterleave(&samples, self.format.number_of_channels))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Name of IEEE float compression type of AIFF-C.
+fn float_compression(bits_per_sample: usize) -> (&'static [u8; 4], &'static [u8]) {
+    if bits_per_sample == 64 {
+        (b"fl64", b"64-bit floating point")
+    } else {
+        (b"fl32", b"32-bit floating point")
+    }
+}
+
+/// FORM header, `COMM` chunk and header of `SSND` chunk.
+/// Linear PCM is written as AIFF and IEEE float as AIFF-C.
+pub fn aiff_header(spec: &WaveSpec, sample_frames: usize, size: usize) -> Result<Vec<u8>> {
+    let aifc = spec.sample_format == SampleFormat::Float;
+    let mut comm = Vec::with_capacity(40);
+    comm.extend_from_slice(&(spec.channels as u16).to_be_bytes());
+    comm.extend_from_slice(&(sample_frames as u32).to_be_bytes());
+    comm.extend_from_slice(&(spec.bits_per_sample as u16).to_be_bytes());
+    comm.extend_from_slice(&f64_to_extended(spec.sample_rate as f64));
+    if aifc {
+        let (compression_type, name) = float_compression(spec.bits_per_sample);
+        comm.extend_from_slice(compression_type);
+        // Pascal string padded to even length
+        comm.push(name.len() as u8);
+        comm.extend_from_slice(name);
+        if name.len() % 2 == 0 {
+            comm.push(0);
+        }
+    }
+
+    let mut head = Vec::with_capacity(comm.len() + 46);
+    head.extend_from_slice(b"FORM");
+    // patched at last
+    head.extend_from_slice(&[0; 4]);
+    head.extend_from_slice(if aifc { b"AIFC" } else { b"AIFF" });
+    if aifc {
+        // Format version of AIFF-C
+        head.extend_from_slice(b"FVER");
+        head.extend_from_slice(&4_u32.to_be_bytes());
+        head.extend_from_slice(&0xA2805140_u32.to_be_bytes());
+    }
+    head.extend_from_slice(b"COMM");
+    head.extend_from_slice(&(comm.len() as u32).to_be_bytes());
+    head.extend_from_slice(&comm);
+    head.extend_from_slice(b"SSND");
+    head.extend_from_slice(&((size + 8) as u32).to_be_bytes());
+    // Offset and block size
+    head.extend_from_slice(&[0; 8]);
+
+    let form_size = head.len() - 8 + size + size % 2;
+    let form_size = u32::try_from(form_size).map_err(|_| Error::FormSizeLimit {
+        size: form_size + 8,
+    })?;
+    head[4..8].copy_from_slice(&form_size.to_be_bytes());
+
+    Ok(head)
+}
+
+/// Writer of AIFF which accepts samples block by block and patches sizes in the header at last.
+#[derive(Debug)]
+pub struct AiffWriter<W: Write + Seek> {
+    writer: W,
+    spec: WaveSpec,
+    /// bytes of the header
+    head_size: usize,
+    /// bytes of samples written so far
+    size: usize,
+    /// quantizer with dither, samples are just scaled if it is not given
+    quantizer: Option<Quantizer>,
+    finalized: bool,
+}
+
+impl<W: Write + Seek> AiffWriter<W> {
+    /// Write the header whose sizes are patched by `finalize`.
+    pub fn new(mut writer: W, spec: WaveSpec) -> Result<Self> {
+        // samples of 8, 16, 24 and 32 bits and IEEE float are checked by encoding nothing
+        encode_samples(&[], spec.sample_format, spec.bits_per_sample)?;
+        let head = aiff_header(&spec, 0, 0)?;
+        writer.write_all(&head)?;
+
+        Ok(AiffWriter {
+            writer,
+            spec,
+            head_size: head.len(),
+            size: 0,
+            quantizer: None,
+            finalized: false,
+        })
+    }
+
+    /// Writer which quantizes samples with dither and noise shaping.
+    pub fn with_dither(mut self, config: DitherConfig) -> Self {
+        self.quantizer = Some(Quantizer::new(config, self.spec.channels));
+        self
+    }
+
+    pub fn spec(&self) -> &WaveSpec {
+        &self.spec
+    }
+
+    /// Number of frames written so far.
+    pub fn len(&self) -> usize {
+        self.size / self.spec.block_align().max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Write samples of each channel, shorter channels are padded with silence.
+    /// Nothing is written if the file would exceed the limit of FORM chunk.
+    pub fn write_frames<T: AsRef<[f64]>>(&mut self, buffer: &[T]) -> Result<()> {
+        if buffer.len() != self.spec.channels {
+            return Err(Error::ChannelMismatch {
+                expected: self.spec.channels,
+                found: buffer.len(),
+            });
+        }
+        let buffer: Vec<Vec<f64>> = buffer.iter().map(|c| c.as_ref().to_vec()).collect();
+        let interleaved = interleave(&buffer);
+        let mut bytes = match self.quantizer.as_mut() {
+            Some(quantizer) => encode_samples_dithered(
+                &interleaved,
+                self.spec.sample_format,
+                self.spec.bits_per_sample,
+                quantizer,
+            )?,
+            None => encode_samples(
+                &interleaved,
+                self.spec.sample_format,
+                self.spec.bits_per_sample,
+            )?,
+        };
+        let width = self.spec.bits_per_sample / 8;
+        swap_bytes(&mut bytes, width);
+        if width == 1 {
+            flip_sign_bits(&mut bytes);
+        }
+        let form_size = self.head_size + self.size + bytes.len();
+        if u32::try_from(form_size).is_err() {
+            return Err(Error::FormSizeLimit { size: form_size });
+        }
+        self.writer.write_all(&bytes)?;
+        self.size += bytes.len();
+
+        Ok(())
+    }
+
+    /// Write the pad byte and rewrite the header with the sizes.
+    fn patch_header(&mut self) -> Result<()> {
+        if self.size % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        let head = aiff_header(&self.spec, self.len(), self.size)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&head)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Complete the file, which is also done on drop ignoring errors.
+    pub fn finalize(mut self) -> Result<()> {
+        self.finalized = true;
+        self.patch_header()
+    }
+}
+
+impl<W: Write + Seek> Drop for AiffWriter<W> {
+    fn drop(&mut self) {
+        if !self.finalized {
+            let _ = self.patch_header();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn extended_sample_rates() {
+        // 44100 Hz of files written by macOS
+        let bytes = [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0];
+        assert_eq!(44100.0, extended_to_f64(bytes));
+        assert_eq!(bytes, f64_to_extended(44100.0));
+        for rate in [8000.0, 22050.0, 48000.0, 96000.0, 11025.5, 1.0] {
+            assert_eq!(rate, extended_to_f64(f64_to_extended(rate)));
+        }
+        assert_eq!(0.0, extended_to_f64(f64_to_extended(0.0)));
+    }
+
+    #[test]
+    fn reject_comm_out_of_range() {
+        let comm = |channels: u16, rate: [u8; 10]| {
+            let mut comm = channels.to_be_bytes().to_vec();
+            comm.extend(1000_u32.to_be_bytes());
+            comm.extend(16_u16.to_be_bytes());
+            comm.extend(rate);
+            comm
+        };
+        let rate = f64_to_extended(44100.0);
+        assert!(AiffFormat::parse(&comm(2, rate), false, 12).is_ok());
+        assert!(matches!(
+            AiffFormat::parse(&comm(0, rate), false, 12),
+            Err(Error::InvalidChannels(0))
+        ));
+        // infinity, NaN, zero, negative and the largest exponent
+        for rate in [
+            [0x7F, 0xFF, 0x80, 0, 0, 0, 0, 0, 0, 0],
+            [0x7F, 0xFF, 0xC0, 0, 0, 0, 0, 0, 0, 0],
+            f64_to_extended(0.0),
+            f64_to_extended(-44100.0),
+            [0x7F, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        ] {
+            assert!(matches!(
+                AiffFormat::parse(&comm(2, rate), false, 12),
+                Err(Error::UnsupportedSampleRate(_, "AIFF"))
+            ));
+        }
+    }
+
+    #[test]
+    fn write_read_formats() {
+        let buffer: Vec<Vec<f64>> = (0..2)
+            .map(|c| {
+                (0..1001)
+                    .map(|i| ((i + c * 5) as f64 * 0.03).sin() * 0.7)
+                    .collect()
+            })
+            .collect();
+        for (sample_format, bits_per_sample) in [
+            (SampleFormat::Int, 8),
+            (SampleFormat::Int, 16),
+            (SampleFormat::Int, 24),
+            (SampleFormat::Int, 32),
+            (SampleFormat::Float, 32),
+            (SampleFormat::Float, 64),
+        ] {
+            let spec = WaveSpec {
+                channels: 2,
+                sample_rate: 44100,
+                sample_format,
+                bits_per_sample,
+            };
+            let mut buf = Cursor::new(Vec::new());
+            let mut writer = AiffWriter::new(&mut buf, spec).unwrap();
+            for (left, right) in buffer[0].chunks(300).zip(buffer[1].chunks(300)) {
+                writer.write_frames(&[left, right]).unwrap();
+            }
+            writer.finalize().unwrap();
+
+            let buf = buf.into_inner();
+            // the first sample of the left channel is silence
+            assert_eq!(0, buf.len() % 2);
+            let mut reader = AiffReader::new(Cursor::new(buf)).unwrap();
+            assert_eq!(spec, reader.spec());
+            assert_eq!(1001, reader.duration());
+            let mut result = vec![Vec::new(); 2];
+            loop {
+                let block = reader.read_frames(400).unwrap();
+                if block[0].is_empty() {
+                    break;
+                }
+                for (channel, samples) in result.iter_mut().zip(block) {
+                    channel.extend(samples);
+                }
+            }
+            let tolerance = 2.0_f64.powi(1 - bits_per_sample as i32).max(1e-7);
+            for (expected, result) in buffer.iter().zip(result.iter()) {
+                assert_eq!(expected.len(), result.len());
+                for (a, b) in expected.iter().zip(result.iter()) {
+                    assert!(
+                        (a - b).abs() <= tolerance,
+                        "{:?} {}",
+                        sample_format,
+                        bits_per_sample
+                    );
+                }
+            }
+        }
+    }
+
+    /// AIFF-C file of a monaural sample, `comm` is followed by the compression type.
+    fn aifc(compression_type: &[u8], sample_size: u16, data: &[u8]) -> Vec<u8> {
+        let mut comm = 1_u16.to_be_bytes().to_vec();
+        // G.711 samples are a byte each whatever the sample size is
+        let width = match compression_type {
+            b"ulaw" | b"alaw" => 1,
+            _ => (sample_size as u32).div_ceil(8),
+        };
+        comm.extend((data.len() as u32 / width).to_be_bytes());
+        comm.extend(sample_size.to_be_bytes());
+        comm.extend(f64_to_extended(8000.0));
+        comm.extend(compression_type);
+        comm.extend([0, 0]);
+
+        let mut buf = b"FORM\x00\x00\x00\x00AIFC".to_vec();
+        buf.extend(b"COMM");
+        buf.extend((comm.len() as u32).to_be_bytes());
+        buf.extend(comm);
+        buf.extend(b"SSND");
+        buf.extend((data.len() as u32 + 12).to_be_bytes());
+        // offset of 4 bytes
+        buf.extend([0, 0, 0, 4, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        buf.extend(data);
+        let form_size = buf.len() as u32 - 8;
+        buf[4..8].copy_from_slice(&form_size.to_be_bytes());
+
+        buf
+    }
+
+    #[test]
+    fn read_compression_types() {
+        let read = |buf: Vec<u8>| {
+            let mut reader = AiffReader::new(Cursor::new(buf)).unwrap();
+            reader.read_frames(100).unwrap().remove(0)
+        };
+        assert_eq!(
+            vec![0.5, -1.0],
+            read(aifc(b"sowt", 16, &[0, 0x40, 0, 0x80]))
+        );
+        assert_eq!(
+            vec![0.5, -1.0],
+            read(aifc(b"twos", 16, &[0x40, 0, 0x80, 0]))
+        );
+        assert_eq!(vec![0.5, -1.0], read(aifc(b"NONE", 8, &[0x40, 0x80])));
+        assert_eq!(vec![0.5, -1.0], read(aifc(b"raw ", 8, &[0xC0, 0x00])));
+        // left-justified 12 bits
+        assert_eq!(vec![0.5], read(aifc(b"NONE", 12, &[0x40, 0x00])));
+        assert_eq!(vec![0.5], read(aifc(b"fl32", 32, &0.5_f32.to_be_bytes())));
+        let ulaw = Law::MuLaw.encode(8192);
+        assert_eq!(
+            vec![Law::MuLaw.decode(ulaw).to_normalized()],
+            read(aifc(b"ulaw", 16, &[ulaw]))
+        );
+
+        assert!(matches!(
+            AiffReader::new(Cursor::new(aifc(b"ima4", 16, &[0; 34]))),
+            Err(Error::UnsupportedCompressionType { offset: 38, .. })
+        ));
+        assert!(matches!(
+            AiffReader::new(Cursor::new(b"RIFF\x00\x00\x00\x00WAVE".to_vec())),
+            Err(Error::BadMagic { offset: 0, .. })
+        ));
+    }
+}
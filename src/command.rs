@@ -1,10 +1,10 @@
-use clap::{Parser, ValueEnum};
+use clap::Parser;
 
-#[derive(Clone, Debug, ValueEnum, PartialEq, Parser)]
-pub enum Mode {
-    TimeStretch,
-    PitchShift,
-}
+use phase_gradient_vocoder::dither::{Dither, NoiseShaping};
+use phase_gradient_vocoder::peak::PeakMode;
+use phase_gradient_vocoder::raw::RawFormat;
+use phase_gradient_vocoder::sampler::LoopStretch;
+use phase_gradient_vocoder::{ChannelLink, Mode, Scheme};
 
 #[derive(Parser, Debug)]
 #[command(about, long_about = None)]
@@ -16,13 +16,52 @@ pub struct Args {
     /// factor ratio
     #[arg(short, long)]
     pub ratio: f64,
-    /// input wave file path
+    /// input wave, AIFF, FLAC or MP3, AAC and Ogg Vorbis file path, `-` reads stdin
     #[arg(short, long)]
-    pub i: Option<String>,
-    /// output wave file path
+    pub i: String,
+    /// output wave, AIFF or FLAC file path, whose format follows the extension, `-` writes wave to stdout
     #[arg(short, long)]
     pub o: Option<String>,
     /// frame size that should be power of two
     #[arg(short, long)]
     pub buffer: Option<usize>,
+    /// differentiation scheme of phase in time direction, real-time one has no lookahead
+    #[arg(value_enum, long)]
+    pub scheme: Option<Scheme>,
+    /// phase integration of multiple channels, linked ones keep the stereo image
+    #[arg(value_enum, long)]
+    pub link: Option<ChannelLink>,
+    /// compress output wave file with IMA ADPCM
+    #[arg(long, conflicts_with = "raw")]
+    pub ima_adpcm: bool,
+    /// compand output wave file with G.711 law of the input one
+    #[arg(long, conflicts_with = "raw")]
+    pub g711: bool,
+    /// dither added when samples are quantized into linear PCM
+    #[arg(value_enum, long)]
+    pub dither: Option<Dither>,
+    /// filter of quantization error, it implies TPDF dither unless dither is given
+    #[arg(value_enum, long)]
+    pub noise_shaping: Option<NoiseShaping>,
+    /// seed of random phase and dither, the same one gives the same output
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// management of peaks exceeding full scale, clipped ones are reported in every mode
+    #[arg(value_enum, long)]
+    pub peak: Option<PeakMode>,
+    /// target of normalization and ceiling of limiter and soft clipping in dBFS
+    #[arg(long, allow_negative_numbers = true)]
+    pub ceiling: Option<f64>,
+    /// stretch of loops of sampler chunk, circular ones keep the loop boundary seamless
+    #[arg(value_enum, long)]
+    pub loop_stretch: Option<LoopStretch>,
+    /// headerless samples of input and output in this format, the output path defaults to `./output.raw`
+    #[arg(value_enum, long, requires = "rate", requires = "channels")]
+    pub raw: Option<RawFormat>,
+    /// sample rate of headerless samples
+    #[arg(long, requires = "raw")]
+    pub rate: Option<usize>,
+    /// number of channels of headerless samples
+    #[arg(long, requires = "raw")]
+    pub channels: Option<usize>,
 }
@@ -0,0 +1,160 @@
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Noise added to samples before quantization.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum Dither {
+    /// no dither, samples are just rounded
+    None,
+    /// triangular probability density function of ±1 LSB
+    Tpdf,
+}
+
+/// Filter of the quantization error fed back into the following samples.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum NoiseShaping {
+    /// white quantization noise
+    None,
+    /// noise transfer function of 1 - z^-1
+    FirstOrder,
+    /// noise transfer function of (1 - z^-1)^2
+    SecondOrder,
+    /// 5-tap filter by Lipshitz et al. weighted by the threshold of hearing at 44.1 kHz
+    Lipshitz,
+}
+
+impl NoiseShaping {
+    /// Coefficients of past errors from the latest one.
+    pub fn coefficients(&self) -> &'static [f64] {
+        match self {
+            NoiseShaping::None => &[],
+            NoiseShaping::FirstOrder => &[1.0],
+            NoiseShaping::SecondOrder => &[2.0, -1.0],
+            NoiseShaping::Lipshitz => &[2.033, -2.165, 1.959, -1.590, 0.6149],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DitherConfig {
+    pub dither: Dither,
+    pub noise_shaping: NoiseShaping,
+    /// seed of dither, the same one gives the same output
+    pub seed: u64,
+}
+
+impl Default for DitherConfig {
+    fn default() -> Self {
+        DitherConfig {
+            dither: Dither::Tpdf,
+            noise_shaping: NoiseShaping::None,
+            seed: 0,
+        }
+    }
+}
+
+/// Bound of the error fed back, so that clipped samples do not make the filter unstable.
+const MAX_ERROR: f64 = 2.0;
+
+/// Quantizer which keeps the errors of each channel.
+#[derive(Debug, Clone)]
+pub struct Quantizer {
+    config: DitherConfig,
+    rng: StdRng,
+    /// past errors of each channel from the latest one
+    errors: Vec<Vec<f64>>,
+}
+
+impl Quantizer {
+    pub fn new(config: DitherConfig, channels: usize) -> Self {
+        let order = config.noise_shaping.coefficients().len();
+        Quantizer {
+            config,
+            rng: StdRng::seed_from_u64(config.seed),
+            errors: vec![vec![0.0; order]; channels.max(1)],
+        }
+    }
+
+    pub fn config(&self) -> &DitherConfig {
+        &self.config
+    }
+
+    pub fn channels(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Round `value` in units of LSB into an integer between `min` and `max`.
+    pub fn quantize(&mut self, value: f64, channel: usize, min: f64, max: f64) -> f64 {
+        let errors = &mut self.errors[channel];
+        let feedback: f64 = self
+            .config
+            .noise_shaping
+            .coefficients()
+            .iter()
+            .zip(errors.iter())
+            .map(|(coefficient, error)| coefficient * error)
+            .sum();
+        let shaped = value - feedback;
+        let dither = match self.config.dither {
+            Dither::None => 0.0,
+            Dither::Tpdf => self.rng.gen::<f64>() - self.rng.gen::<f64>(),
+        };
+        let quantized = (shaped + dither).round().clamp(min, max);
+
+        if !errors.is_empty() {
+            errors.rotate_right(1);
+            errors[0] = (quantized - shaped).clamp(-MAX_ERROR, MAX_ERROR);
+        }
+
+        quantized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn tpdf_dither_keeps_quiet_signal() {
+        let config = DitherConfig {
+            dither: Dither::None,
+            ..Default::default()
+        };
+        let mut quantizer = Quantizer::new(config, 1);
+        let sum: f64 = (0..10000)
+            .map(|_| quantizer.quantize(0.3, 0, -128.0, 127.0))
+            .sum();
+        assert_eq!(0.0, sum);
+
+        let mut quantizer = Quantizer::new(DitherConfig::default(), 1);
+        let outputs: Vec<f64> = (0..10000)
+            .map(|_| quantizer.quantize(0.3, 0, -128.0, 127.0))
+            .collect();
+        let mean = outputs.iter().sum::<f64>() / outputs.len() as f64;
+        assert!((mean - 0.3).abs() < 0.05);
+
+        // deterministic
+        let mut quantizer = Quantizer::new(DitherConfig::default(), 1);
+        for output in outputs {
+            assert_eq!(output, quantizer.quantize(0.3, 0, -128.0, 127.0));
+        }
+    }
+
+    #[test]
+    fn first_order_shaping_cancels_low_frequency_error() {
+        let config = DitherConfig {
+            noise_shaping: NoiseShaping::FirstOrder,
+            ..Default::default()
+        };
+        let mut quantizer = Quantizer::new(config, 2);
+        let mut accumulated = [0.0; 2];
+        for i in 0..10000 {
+            for (channel, accumulated) in accumulated.iter_mut().enumerate() {
+                let value = (i as f64 * 0.001 + channel as f64).sin() * 3.7;
+                *accumulated += quantizer.quantize(value, channel, -128.0, 127.0) - value;
+                // the total error is the latest one only
+                assert!(accumulated.abs() <= MAX_ERROR);
+            }
+        }
+    }
+}
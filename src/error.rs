@@ -0,0 +1,79 @@
+use thiserror::Error;
+
+use crate::normalize::SampleFormat;
+use crate::vocoder::VocoderError;
+
+/// Errors of reading, processing and writing audio files.
+/// Offsets are in bytes from the beginning of file.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to read or write file")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Vocoder(#[from] VocoderError),
+    #[error("{name} at byte {offset} needs {size} bytes, but the file ends at byte {file_size}")]
+    TruncatedHeader {
+        name: &'static str,
+        offset: usize,
+        size: usize,
+        file_size: usize,
+    },
+    #[error("expected `{expected}` at byte {offset}, but found {found:?}")]
+    BadMagic {
+        offset: usize,
+        expected: &'static str,
+        found: String,
+    },
+    #[error("`{0}` chunk is not found")]
+    MissingChunk(&'static str),
+    #[error("`fmt ` chunk at byte {offset} lacks fields of {format}")]
+    IncompleteFormatChunk { offset: usize, format: String },
+    #[error("compression code {code:#06x} at byte {offset} is not supported")]
+    UnsupportedCodec { offset: usize, code: usize },
+    #[error("{0} bits per sample of {1:?} format is not supported")]
+    UnsupportedBitsPerSample(usize, SampleFormat),
+    #[error("{found} channels of samples do not match {expected} channels of the format")]
+    ChannelMismatch { expected: usize, found: usize },
+    #[error("FORM chunk of {size} bytes exceeds the limit of 4 GiB")]
+    FormSizeLimit { size: usize },
+    #[error("{channels} channels exceed the limit of {limit} channels of the format")]
+    TooManyChannels { channels: usize, limit: usize },
+    #[error("{0} files need the cargo feature `{1}`")]
+    DisabledFormat(&'static str, &'static str),
+    #[cfg(feature = "flac")]
+    #[error(transparent)]
+    Flac(#[from] claxon::Error),
+    #[cfg(feature = "symphonia")]
+    #[error(transparent)]
+    Symphonia(#[from] symphonia::core::errors::Error),
+    #[error("no audio track is found")]
+    MissingTrack,
+    #[error("{0} codec is not supported")]
+    UnsupportedLossyCodec(&'static str),
+    #[error("{0} files are only read")]
+    ReadOnlyFormat(&'static str),
+    #[error("MD5 signature {found} of decoded samples differs from {expected} of STREAMINFO")]
+    Md5Mismatch { expected: String, found: String },
+    #[error("compression type {name:?} at byte {offset} is not supported")]
+    UnsupportedCompressionType { offset: usize, name: String },
+    #[error("block align {block_align} at byte {offset} is inconsistent, expected {expected}")]
+    InconsistentBlockAlign {
+        offset: usize,
+        block_align: usize,
+        expected: String,
+    },
+    #[error("{0} channels are not supported")]
+    InvalidChannels(usize),
+    #[error("sample rate of {0} Hz is not supported by {1}")]
+    UnsupportedSampleRate(usize, &'static str),
+    #[error(
+        "`data` chunk at byte {offset} has {size} bytes, but the file ends at byte {file_size}"
+    )]
+    DataExceedsFile {
+        offset: usize,
+        size: usize,
+        file_size: usize,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,811 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use md5::{Digest, Md5};
+
+use crate::dither::{DitherConfig, Quantizer};
+use crate::error::{Error, Result};
+use crate::normalize::SampleFormat;
+use crate::spec::WaveSpec;
+use crate::write::{encode_samples, encode_samples_dithered, interleave};
+
+/// Frames of each block written, which is the default of the reference encoder.
+const BLOCK_SIZE: usize = 4096;
+/// Highest order of rice partitions tried by the writer.
+const MAX_PARTITION_ORDER: u32 = 8;
+/// Highest order of fixed predictors.
+const MAX_FIXED_ORDER: usize = 4;
+/// Channels of a FLAC stream.
+const MAX_CHANNELS: usize = 8;
+/// Sample rate in 20 bits of STREAMINFO.
+const MAX_SAMPLE_RATE: usize = (1 << 20) - 1;
+
+/// Hexadecimal digits of MD5 signature.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Little-endian bytes of a sample in `width` bytes, which MD5 signature of FLAC is computed over.
+fn md5_bytes(sample: i32, width: usize) -> impl Iterator<Item = u8> {
+    sample.to_le_bytes().into_iter().take(width)
+}
+
+/// Reader of FLAC which decodes blocks as they are needed and verifies MD5 signature at the end.
+pub struct FlacReader<R: Read> {
+    decoder: claxon::FlacReader<R>,
+    streaminfo: claxon::metadata::StreamInfo,
+    /// buffer of a decoded block, which is reused
+    buffer: Vec<i32>,
+    /// decoded samples of each channel which are not read yet
+    pending: Vec<Vec<f64>>,
+    /// signature of samples decoded so far, which is taken at the end of stream
+    md5: Option<Md5>,
+}
+
+impl<R: Read> std::fmt::Debug for FlacReader<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlacReader")
+            .field("streaminfo", &self.streaminfo)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read> FlacReader<R> {
+    /// Parse metadata blocks, the source is left at the first frame.
+    pub fn new(reader: R) -> Result<Self> {
+        let decoder = claxon::FlacReader::new(reader)?;
+        let streaminfo = decoder.streaminfo();
+
+        Ok(FlacReader {
+            decoder,
+            streaminfo,
+            buffer: Vec::new(),
+            pending: vec![Vec::new(); streaminfo.channels as usize],
+            md5: Some(Md5::new()),
+        })
+    }
+
+    pub fn streaminfo(&self) -> &claxon::metadata::StreamInfo {
+        &self.streaminfo
+    }
+
+    /// Format of linear PCM which decoded samples fit in.
+    pub fn spec(&self) -> WaveSpec {
+        WaveSpec {
+            channels: self.streaminfo.channels as usize,
+            sample_rate: self.streaminfo.sample_rate as usize,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: self.sample_width() * 8,
+        }
+    }
+
+    /// Bytes of a sample.
+    fn sample_width(&self) -> usize {
+        (self.streaminfo.bits_per_sample as usize).div_ceil(8)
+    }
+
+    /// Number of frames of the whole stream, which is 0 if it is unknown.
+    pub fn duration(&self) -> usize {
+        self.streaminfo.samples.unwrap_or(0) as usize
+    }
+
+    /// Decode the next block into `pending`, the signature is verified at the end of stream.
+    fn decode_block(&mut self) -> Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let block = match self.decoder.blocks().read_next_or_eof(buffer)? {
+            Some(block) => block,
+            None => return self.verify(),
+        };
+        let scale = (1_u64 << (self.streaminfo.bits_per_sample - 1)) as f64;
+        let width = self.sample_width();
+        if let Some(md5) = self.md5.as_mut() {
+            let bytes: Vec<u8> = (0..block.duration())
+                .flat_map(|i| (0..block.channels()).map(move |channel| (i, channel)))
+                .flat_map(|(i, channel)| md5_bytes(block.sample(channel, i), width))
+                .collect();
+            md5.update(bytes);
+        }
+        for (channel, pending) in self.pending.iter_mut().enumerate() {
+            pending.extend(
+                block
+                    .channel(channel as u32)
+                    .iter()
+                    .map(|sample| *sample as f64 / scale),
+            );
+        }
+        self.buffer = block.into_buffer();
+
+        Ok(())
+    }
+
+    /// Compare the signature of decoded samples with the one of STREAMINFO, which is unset if it is 0.
+    fn verify(&mut self) -> Result<()> {
+        let found = match self.md5.take() {
+            Some(md5) => md5.finalize(),
+            None => return Ok(()),
+        };
+        let expected = self.streaminfo.md5sum;
+        if expected != [0; 16] && found[..] != expected[..] {
+            return Err(Error::Md5Mismatch {
+                expected: hex(&expected),
+                found: hex(&found),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Read at most `frames` frames as samples of each channel, they are empty at the end.
+    pub fn read_frames(&mut self, frames: usize) -> Result<Vec<Vec<f64>>> {
+        while self.md5.is_some() && self.pending.first().map_or(0, Vec::len) < frames {
+            self.decode_block()?;
+        }
+        let frames = frames.min(self.pending.first().map_or(0, Vec::len));
+
+        Ok(self
+            .pending
+            .iter_mut()
+            .map(|channel| channel.drain(..frames).collect())
+            .collect())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+/// Writer of bits from the most significant one.
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// bits which do not fill a byte yet
+    accumulator: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    /// Write lower `bits` bits of `value`, which are at most 32 bits.
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.accumulator >> self.bits) as u8);
+        }
+        self.accumulator &= (1 << self.bits) - 1;
+    }
+
+    /// Write `value` in two's complement of `bits` bits.
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// Write `value` zeros terminated by a one.
+    fn write_unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    /// Fill the last byte with zeros.
+    fn into_bytes(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+        self.bytes
+    }
+}
+
+/// CRC-8 of frame headers, whose polynomial is x^8 + x^2 + x + 1.
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 of frames, whose polynomial is x^16 + x^15 + x^2 + 1.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Frame number coded like UTF-8, which is extended up to 36 bits.
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    // number of continuation bytes of 6 bits
+    let continuations = (1..6).find(|n| value < 1 << (6 - n + 6 * n)).unwrap_or(6);
+    let mut bytes = vec![0; continuations + 1];
+    for (i, byte) in bytes.iter_mut().enumerate().skip(1).rev() {
+        *byte = 0x80 | ((value >> (6 * (continuations - i))) & 0x3F) as u8;
+    }
+    let lead_bits = value >> (6 * continuations);
+    bytes[0] = (0xFF_u16 << (7 - continuations)) as u8 | lead_bits as u8;
+
+    bytes
+}
+
+/// Residual of fixed polynomial predictor of `order`, following the warm-up samples.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    let s = samples;
+    (order..samples.len())
+        .map(|i| match order {
+            0 => s[i],
+            1 => s[i] - s[i - 1],
+            2 => s[i] - 2 * s[i - 1] + s[i - 2],
+            3 => s[i] - 3 * s[i - 1] + 3 * s[i - 2] - s[i - 3],
+            _ => s[i] - 4 * s[i - 1] + 6 * s[i - 2] - 4 * s[i - 3] + s[i - 4],
+        })
+        .collect()
+}
+
+/// Residual mapped to unsigned values, 0, -1, 1, -2, 2 and so on.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Rice parameter of a partition and its bits, the parameter is estimated from the mean.
+fn rice_parameter(partition: &[i64]) -> (u32, usize) {
+    let sum: u64 = partition.iter().map(|value| zigzag(*value)).sum();
+    let mean = sum / partition.len().max(1) as u64;
+    let estimated = (63 - mean.max(1).leading_zeros()).min(30);
+    let cost = |parameter: u32| {
+        partition.len() * (parameter as usize + 1)
+            + partition
+                .iter()
+                .map(|value| (zigzag(*value) >> parameter) as usize)
+                .sum::<usize>()
+    };
+    [estimated, (estimated + 1).min(30)]
+        .into_iter()
+        .map(|parameter| (parameter, cost(parameter)))
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+/// Partitioned rice coding of residual.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RiceCoding {
+    partition_order: u32,
+    parameters: Vec<u32>,
+}
+
+impl RiceCoding {
+    /// Partition order and parameters of the fewest bits, which are returned with them.
+    fn plan(residual: &[i64], block_size: usize, order: usize) -> (Self, usize) {
+        let mut best: Option<(Self, usize)> = None;
+        for partition_order in 0..=MAX_PARTITION_ORDER {
+            let length = block_size >> partition_order;
+            if !block_size.is_multiple_of(1 << partition_order) || length <= order {
+                break;
+            }
+            // the first partition lacks the warm-up samples
+            let mut start = 0;
+            let mut parameters = Vec::new();
+            let mut bits = 6;
+            for i in 0..1 << partition_order {
+                let end = start + if i == 0 { length - order } else { length };
+                let (parameter, partition_bits) = rice_parameter(&residual[start..end]);
+                parameters.push(parameter);
+                bits += partition_bits;
+                start = end;
+            }
+            let coding = RiceCoding {
+                partition_order,
+                parameters,
+            };
+            bits += coding.parameter_bits() as usize * coding.parameters.len();
+            if best.as_ref().is_none_or(|(_, best_bits)| bits < *best_bits) {
+                best = Some((coding, bits));
+            }
+        }
+
+        best.unwrap()
+    }
+
+    /// Parameters of 5 bits are needed over 14.
+    fn parameter_bits(&self) -> u32 {
+        if self.parameters.iter().any(|parameter| *parameter > 14) {
+            5
+        } else {
+            4
+        }
+    }
+
+    fn write(&self, writer: &mut BitWriter, residual: &[i64], block_size: usize, order: usize) {
+        let parameter_bits = self.parameter_bits();
+        writer.write(if parameter_bits == 5 { 1 } else { 0 }, 2);
+        writer.write(self.partition_order as u64, 4);
+        let length = block_size >> self.partition_order;
+        let mut start = 0;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            let end = start + if i == 0 { length - order } else { length };
+            writer.write(*parameter as u64, parameter_bits);
+            for value in residual[start..end].iter() {
+                let value = zigzag(*value);
+                writer.write_unary(value >> parameter);
+                writer.write(value, *parameter);
+            }
+            start = end;
+        }
+    }
+}
+
+/// Subframe of a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Subframe {
+    Constant,
+    Verbatim,
+    Fixed { order: usize, coding: RiceCoding },
+}
+
+impl Subframe {
+    /// Subframe of the fewest bits for `samples` of `bits` bits, which are returned with it.
+    fn plan(samples: &[i64], bits: u32) -> (Self, usize) {
+        if samples.iter().all(|sample| *sample == samples[0]) {
+            return (Subframe::Constant, 8 + bits as usize);
+        }
+        let mut best = (Subframe::Verbatim, 8 + samples.len() * bits as usize);
+        for order in 0..=MAX_FIXED_ORDER.min(samples.len() - 1) {
+            let residual = fixed_residual(samples, order);
+            let (coding, residual_bits) = RiceCoding::plan(&residual, samples.len(), order);
+            let total = 8 + order * bits as usize + residual_bits;
+            if total < best.1 {
+                best = (Subframe::Fixed { order, coding }, total);
+            }
+        }
+
+        best
+    }
+
+    fn write(&self, writer: &mut BitWriter, samples: &[i64], bits: u32) {
+        match self {
+            Subframe::Constant => {
+                writer.write(0b0000_0000, 8);
+                writer.write_signed(samples[0], bits);
+            }
+            Subframe::Verbatim => {
+                writer.write(0b0000_0010, 8);
+                for sample in samples.iter() {
+                    writer.write_signed(*sample, bits);
+                }
+            }
+            Subframe::Fixed { order, coding } => {
+                writer.write((0b0000_1000 | *order as u64) << 1, 8);
+                for sample in samples[..*order].iter() {
+                    writer.write_signed(*sample, bits);
+                }
+                let residual = fixed_residual(samples, *order);
+                coding.write(writer, &residual, samples.len(), *order);
+            }
+        }
+    }
+}
+
+/// Code of bits per sample in frame headers.
+fn bits_per_sample_code(bits_per_sample: usize) -> u8 {
+    match bits_per_sample {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000,
+    }
+}
+
+/// Encode a frame of samples of each channel, stereo ones are decorrelated if it saves bits.
+fn encode_frame(channels: &[Vec<i64>], bits_per_sample: usize, frame_number: u64) -> Vec<u8> {
+    let block_size = channels[0].len();
+    let bits = bits_per_sample as u32;
+    // channel assignment and subframes with their bits per sample
+    let (assignment, subframes): (u8, Vec<(Vec<i64>, u32)>) = if channels.len() == 2 {
+        let (left, right) = (&channels[0], &channels[1]);
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let cost = |samples: &[i64], bits: u32| Subframe::plan(samples, bits).1;
+        let (left_bits, right_bits) = (cost(left, bits), cost(right, bits));
+        let (side_bits, mid_bits) = (cost(&side, bits + 1), cost(&mid, bits));
+        let candidates = [
+            (0b0001, left_bits + right_bits),
+            (0b1000, left_bits + side_bits),
+            (0b1001, side_bits + right_bits),
+            (0b1010, mid_bits + side_bits),
+        ];
+        let assignment = candidates.iter().min_by_key(|(_, bits)| *bits).unwrap().0;
+        let subframes = match assignment {
+            0b1000 => vec![(left.clone(), bits), (side, bits + 1)],
+            0b1001 => vec![(side, bits + 1), (right.clone(), bits)],
+            0b1010 => vec![(mid, bits), (side, bits + 1)],
+            _ => vec![(left.clone(), bits), (right.clone(), bits)],
+        };
+        (assignment, subframes)
+    } else {
+        (
+            channels.len() as u8 - 1,
+            channels
+                .iter()
+                .map(|samples| (samples.clone(), bits))
+                .collect(),
+        )
+    };
+
+    // frame header of fixed block size, whose sample rate is the one of STREAMINFO
+    let mut frame = vec![0xFF, 0xF8];
+    let block_size_code = match block_size {
+        BLOCK_SIZE => 0b1100,
+        1..=256 => 0b0110,
+        _ => 0b0111,
+    };
+    frame.push(block_size_code << 4);
+    frame.push(assignment << 4 | bits_per_sample_code(bits_per_sample) << 1);
+    frame.extend(utf8_number(frame_number));
+    match block_size_code {
+        0b0110 => frame.push((block_size - 1) as u8),
+        0b0111 => frame.extend(((block_size - 1) as u16).to_be_bytes()),
+        _ => {}
+    }
+    frame.push(crc8(&frame));
+
+    let mut writer = BitWriter::default();
+    for (samples, bits) in subframes.iter() {
+        let (subframe, _) = Subframe::plan(samples, *bits);
+        subframe.write(&mut writer, samples, *bits);
+    }
+    frame.extend(writer.into_bytes());
+    frame.extend(crc16(&frame).to_be_bytes());
+
+    frame
+}
+
+/// STREAMINFO metadata block, `frame_sizes` are the minimum and maximum ones.
+fn streaminfo(
+    spec: &WaveSpec,
+    frame_sizes: (usize, usize),
+    total_frames: usize,
+    md5: &[u8],
+) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    // the last metadata block of type 0 and 34 bytes
+    writer.write(0x80, 8);
+    writer.write(34, 24);
+    writer.write(BLOCK_SIZE as u64, 16);
+    writer.write(BLOCK_SIZE as u64, 16);
+    writer.write(frame_sizes.0 as u64, 24);
+    writer.write(frame_sizes.1 as u64, 24);
+    writer.write(spec.sample_rate as u64, 20);
+    writer.write(spec.channels as u64 - 1, 3);
+    writer.write(spec.bits_per_sample as u64 - 1, 5);
+    // 0 means unknown if it exceeds 36 bits
+    let total_frames = if total_frames < 1 << 36 {
+        total_frames as u64
+    } else {
+        0
+    };
+    writer.write(total_frames >> 32, 4);
+    writer.write(total_frames, 32);
+    let mut bytes = writer.into_bytes();
+    bytes.extend_from_slice(md5);
+
+    bytes
+}
+
+/// Writer of FLAC which accepts samples block by block and patches STREAMINFO at last.
+#[derive(Debug)]
+pub struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    spec: WaveSpec,
+    /// quantizer with dither, samples are just scaled if it is not given
+    quantizer: Option<Quantizer>,
+    /// quantized samples of each channel which do not fill a block yet
+    pending: Vec<Vec<i64>>,
+    frame_number: u64,
+    /// frames written so far including pending ones
+    total_frames: usize,
+    /// minimum and maximum bytes of frames
+    frame_sizes: Option<(usize, usize)>,
+    md5: Md5,
+    finalized: bool,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// Write the metadata which is patched by `finalize`, samples are linear PCM of 8, 16 or 24 bits.
+    pub fn new(mut writer: W, spec: WaveSpec) -> Result<Self> {
+        if spec.sample_format != SampleFormat::Int || ![8, 16, 24].contains(&spec.bits_per_sample) {
+            return Err(Error::UnsupportedBitsPerSample(
+                spec.bits_per_sample,
+                spec.sample_format,
+            ));
+        }
+        if spec.channels == 0 {
+            return Err(Error::InvalidChannels(spec.channels));
+        }
+        if spec.channels > MAX_CHANNELS {
+            return Err(Error::TooManyChannels {
+                channels: spec.channels,
+                limit: MAX_CHANNELS,
+            });
+        }
+        if !(1..=MAX_SAMPLE_RATE).contains(&spec.sample_rate) {
+            return Err(Error::UnsupportedSampleRate(spec.sample_rate, "FLAC"));
+        }
+        writer.write_all(b"fLaC")?;
+        writer.write_all(&streaminfo(&spec, (0, 0), 0, &[0; 16]))?;
+
+        Ok(FlacWriter {
+            writer,
+            spec,
+            quantizer: None,
+            pending: vec![Vec::new(); spec.channels],
+            frame_number: 0,
+            total_frames: 0,
+            frame_sizes: None,
+            md5: Md5::new(),
+            finalized: false,
+        })
+    }
+
+    /// Writer which quantizes samples with dither and noise shaping.
+    pub fn with_dither(mut self, config: DitherConfig) -> Self {
+        self.quantizer = Some(Quantizer::new(config, self.spec.channels));
+        self
+    }
+
+    pub fn spec(&self) -> &WaveSpec {
+        &self.spec
+    }
+
+    /// Number of frames written so far.
+    pub fn len(&self) -> usize {
+        self.total_frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_frames == 0
+    }
+
+    /// Write samples of each channel, shorter channels are padded with silence.
+    /// Samples are encoded when they fill a block.
+    pub fn write_frames<T: AsRef<[f64]>>(&mut self, buffer: &[T]) -> Result<()> {
+        if buffer.len() != self.spec.channels {
+            return Err(Error::ChannelMismatch {
+                expected: self.spec.channels,
+                found: buffer.len(),
+            });
+        }
+        let buffer: Vec<Vec<f64>> = buffer.iter().map(|c| c.as_ref().to_vec()).collect();
+        let interleaved = interleave(&buffer);
+        let mut bytes = match self.quantizer.as_mut() {
+            Some(quantizer) => encode_samples_dithered(
+                &interleaved,
+                self.spec.sample_format,
+                self.spec.bits_per_sample,
+                quantizer,
+            )?,
+            None => encode_samples(
+                &interleaved,
+                self.spec.sample_format,
+                self.spec.bits_per_sample,
+            )?,
+        };
+        let width = self.spec.bits_per_sample / 8;
+        if width == 1 {
+            // unsigned samples of WAVE
+            for byte in bytes.iter_mut() {
+                *byte ^= 0x80;
+            }
+        }
+        self.md5.update(&bytes);
+        let shift = 32 - 8 * width as u32;
+        for (i, sample) in bytes.chunks_exact(width).enumerate() {
+            let mut le_bytes = [0; 4];
+            le_bytes[..width].copy_from_slice(sample);
+            let sample = (i32::from_le_bytes(le_bytes) << shift) >> shift;
+            self.pending[i % self.spec.channels].push(sample as i64);
+        }
+        self.total_frames += bytes.len() / self.spec.block_align().max(1);
+
+        while self.pending[0].len() >= BLOCK_SIZE {
+            self.write_block(BLOCK_SIZE)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the first `frames` pending frames.
+    fn write_block(&mut self, frames: usize) -> Result<()> {
+        let block: Vec<Vec<i64>> = self
+            .pending
+            .iter_mut()
+            .map(|channel| channel.drain(..frames).collect())
+            .collect();
+        let frame = encode_frame(&block, self.spec.bits_per_sample, self.frame_number);
+        self.writer.write_all(&frame)?;
+        self.frame_number += 1;
+        let (min, max) = self.frame_sizes.unwrap_or((frame.len(), frame.len()));
+        self.frame_sizes = Some((min.min(frame.len()), max.max(frame.len())));
+
+        Ok(())
+    }
+
+    /// Encode the last block and rewrite STREAMINFO with sizes and the signature.
+    fn patch_header(&mut self) -> Result<()> {
+        let frames = self.pending[0].len();
+        if frames > 0 {
+            self.write_block(frames)?;
+        }
+        let md5 = self.md5.clone().finalize();
+        let head = streaminfo(
+            &self.spec,
+            self.frame_sizes.unwrap_or((0, 0)),
+            self.total_frames,
+            &md5,
+        );
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&head)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Complete the file, which is also done on drop ignoring errors.
+    pub fn finalize(mut self) -> Result<()> {
+        self.finalized = true;
+        self.patch_header()
+    }
+}
+
+impl<W: Write + Seek> Drop for FlacWriter<W> {
+    fn drop(&mut self) {
+        if !self.finalized {
+            let _ = self.patch_header();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_read(buffer: &[Vec<f64>], bits_per_sample: usize) -> (Vec<u8>, Vec<Vec<f64>>) {
+        let spec = WaveSpec {
+            channels: buffer.len(),
+            sample_rate: 96000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample,
+        };
+        let mut buf = Cursor::new(Vec::new());
+        let mut writer = FlacWriter::new(&mut buf, spec).unwrap();
+        let blocks: Vec<Vec<&[f64]>> = buffer.iter().map(|c| c.chunks(3000).collect()).collect();
+        for i in 0..blocks[0].len() {
+            let block: Vec<&[f64]> = blocks.iter().map(|c| c[i]).collect();
+            writer.write_frames(&block).unwrap();
+        }
+        writer.finalize().unwrap();
+        let buf = buf.into_inner();
+
+        let mut reader = FlacReader::new(Cursor::new(buf.clone())).unwrap();
+        assert_eq!(spec, reader.spec());
+        assert_eq!(buffer[0].len(), reader.duration());
+        let mut result = vec![Vec::new(); buffer.len()];
+        loop {
+            let block = reader.read_frames(5000).unwrap();
+            if block[0].is_empty() {
+                break;
+            }
+            for (channel, samples) in result.iter_mut().zip(block) {
+                channel.extend(samples);
+            }
+        }
+
+        (buf, result)
+    }
+
+    #[test]
+    fn write_read_bits_and_channels() {
+        let tone = |phase: f64| -> Vec<f64> {
+            (0..10000)
+                .map(|i| (i as f64 * 0.01 + phase).sin() * 0.6 + (i as f64 * 0.37).sin() * 0.01)
+                .collect()
+        };
+        for bits_per_sample in [8, 16, 24] {
+            // independent, correlated stereo, silence and more channels
+            for buffer in [
+                vec![tone(0.0)],
+                vec![tone(0.0), tone(0.1)],
+                vec![tone(0.0), vec![0.0; 10000]],
+                vec![tone(0.0), tone(1.0), tone(2.0)],
+            ] {
+                let (_, result) = write_read(&buffer, bits_per_sample);
+                let tolerance = 2.0_f64.powi(1 - bits_per_sample as i32);
+                for (expected, result) in buffer.iter().zip(result.iter()) {
+                    assert_eq!(expected.len(), result.len());
+                    for (a, b) in expected.iter().zip(result.iter()) {
+                        assert!((a - b).abs() <= tolerance);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compress_and_verify_signature() {
+        let buffer: Vec<Vec<f64>> = (0..2)
+            .map(|c| {
+                (0..20000)
+                    .map(|i| (i as f64 * 0.02 + c as f64 * 0.05).sin() * 0.5)
+                    .collect()
+            })
+            .collect();
+        let (mut buf, _) = write_read(&buffer, 16);
+        // smooth samples are far smaller than linear PCM
+        assert!(buf.len() < 20000 * 2 * 2 / 2);
+
+        // MD5 signature follows the total number of frames
+        buf[4 + 4 + 18] ^= 0xFF;
+        let mut reader = FlacReader::new(Cursor::new(buf)).unwrap();
+        let result = (0..10).try_for_each(|_| reader.read_frames(5000).map(|_| ()));
+        assert!(matches!(result, Err(Error::Md5Mismatch { .. })));
+    }
+
+    #[test]
+    fn reject_streaminfo_out_of_range() {
+        let spec = WaveSpec {
+            channels: 2,
+            sample_rate: 44100,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 16,
+        };
+        let new = |spec| FlacWriter::new(Cursor::new(Vec::new()), spec);
+        assert!(matches!(
+            new(WaveSpec {
+                channels: 0,
+                ..spec
+            }),
+            Err(Error::InvalidChannels(0))
+        ));
+        for sample_rate in [0, 1 << 20] {
+            assert!(matches!(
+                new(WaveSpec {
+                    sample_rate,
+                    ..spec
+                }),
+                Err(Error::UnsupportedSampleRate(_, "FLAC"))
+            ));
+        }
+        assert!(new(WaveSpec {
+            sample_rate: MAX_SAMPLE_RATE,
+            ..spec
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn frame_numbers_like_utf8() {
+        assert_eq!(vec![0x7F], utf8_number(0x7F));
+        assert_eq!(vec![0xC2, 0x80], utf8_number(0x80));
+        assert_eq!(vec![0xE0, 0xA0, 0x80], utf8_number(0x800));
+        assert_eq!(vec![0xEF, 0xBF, 0xBF], utf8_number(0xFFFF));
+        assert_eq!(vec![0xF0, 0x90, 0x80, 0x80], utf8_number(0x10000));
+    }
+}
@@ -0,0 +1,321 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::aiff::{AiffEncoding, AiffReader, AiffWriter};
+use crate::dither::DitherConfig;
+use crate::error::Result;
+#[cfg(feature = "flac")]
+use crate::flac::{FlacReader, FlacWriter};
+use crate::g711::Law;
+#[cfg(feature = "symphonia")]
+use crate::lossy::LossyReader;
+use crate::metadata::MetadataChunk;
+use crate::reader::WaveReader;
+use crate::spec::WaveSpec;
+use crate::writer::WaveWriter;
+
+/// Container format of audio files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// RIFF WAVE, RF64 and BW64
+    Wave,
+    /// AIFF and AIFF-C
+    Aiff,
+    /// FLAC, which needs the cargo feature `flac`
+    Flac,
+    /// MP3, AAC, Ogg and Matroska, which are only read with the cargo feature `symphonia`
+    Lossy,
+}
+
+impl FileFormat {
+    /// Format given by the magic of the first 12 bytes.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"fLaC") {
+            return Some(FileFormat::Flac);
+        }
+        // ID3 tag, sync word of MPEG audio or ADTS, Ogg page and EBML header of Matroska
+        if header.starts_with(b"ID3")
+            || header.starts_with(b"OggS")
+            || header.starts_with(&[0x1a, 0x45, 0xdf, 0xa3])
+            || matches!(header, [0xff, second, ..] if second & 0xe0 == 0xe0)
+        {
+            return Some(FileFormat::Lossy);
+        }
+        match (header.get(0..4)?, header.get(4..8)?, header.get(8..12)?) {
+            (b"RIFF" | b"RF64" | b"BW64", _, b"WAVE") => Some(FileFormat::Wave),
+            (b"FORM", _, b"AIFF" | b"AIFC") => Some(FileFormat::Aiff),
+            // ISO base media file of MP4 and M4A
+            (_, b"ftyp", _) => Some(FileFormat::Lossy),
+            _ => None,
+        }
+    }
+
+    /// Format given by the extension of `path`, which is case insensitive.
+    pub fn from_extension(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "wav" | "wave" => Some(FileFormat::Wave),
+            "aif" | "aiff" | "aifc" => Some(FileFormat::Aiff),
+            "flac" => Some(FileFormat::Flac),
+            "mp3" | "aac" | "m4a" | "mp4" | "ogg" | "oga" | "opus" | "mka" | "webm" => {
+                Some(FileFormat::Lossy)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reader of any supported format detected from the file magic.
+#[derive(Debug)]
+pub enum AudioReader<R: Read> {
+    Wave(Box<WaveReader<R>>),
+    Aiff(AiffReader<R>),
+    #[cfg(feature = "flac")]
+    Flac(Box<FlacReader<R>>),
+    #[cfg(feature = "symphonia")]
+    Lossy(Box<LossyReader>),
+}
+
+impl<R: Read + Seek> AudioReader<R> {
+    /// Detect the format and parse the header, files of unknown magic are read as WAVE.
+    pub fn new(mut reader: R) -> Result<Self> {
+        match detect_format(&mut reader)? {
+            Some(FileFormat::Aiff) => Ok(AudioReader::Aiff(AiffReader::new(reader)?)),
+            #[cfg(feature = "flac")]
+            Some(FileFormat::Flac) => Ok(AudioReader::Flac(Box::new(FlacReader::new(reader)?))),
+            #[cfg(not(feature = "flac"))]
+            Some(FileFormat::Flac) => Err(crate::error::Error::DisabledFormat("FLAC", "flac")),
+            #[cfg(feature = "symphonia")]
+            Some(FileFormat::Lossy) => Ok(AudioReader::Lossy(Box::new(LossyReader::new(reader)?))),
+            #[cfg(not(feature = "symphonia"))]
+            Some(FileFormat::Lossy) => Err(crate::error::Error::DisabledFormat(
+                "MP3, AAC, Ogg and Matroska",
+                "symphonia",
+            )),
+            _ => Ok(AudioReader::Wave(Box::new(WaveReader::new(reader)?))),
+        }
+    }
+
+    pub fn file_format(&self) -> FileFormat {
+        match self {
+            AudioReader::Wave(_) => FileFormat::Wave,
+            AudioReader::Aiff(_) => FileFormat::Aiff,
+            #[cfg(feature = "flac")]
+            AudioReader::Flac(_) => FileFormat::Flac,
+            #[cfg(feature = "symphonia")]
+            AudioReader::Lossy(_) => FileFormat::Lossy,
+        }
+    }
+
+    pub fn spec(&self) -> WaveSpec {
+        match self {
+            AudioReader::Wave(reader) => reader.spec(),
+            AudioReader::Aiff(reader) => reader.spec(),
+            #[cfg(feature = "flac")]
+            AudioReader::Flac(reader) => reader.spec(),
+            #[cfg(feature = "symphonia")]
+            AudioReader::Lossy(reader) => reader.spec(),
+        }
+    }
+
+    /// Chunks which are carried to the output, only WAVE has them.
+    pub fn metadata(&self) -> &[MetadataChunk] {
+        match self {
+            AudioReader::Wave(reader) => reader.metadata(),
+            _ => &[],
+        }
+    }
+
+    /// Law of G.711 samples.
+    pub fn companding_law(&self) -> Option<Law> {
+        match self {
+            AudioReader::Wave(reader) => reader.format().companding_law,
+            AudioReader::Aiff(reader) => match reader.format().encoding {
+                AiffEncoding::G711(law) => Some(law),
+                _ => None,
+            },
+            #[cfg(feature = "flac")]
+            AudioReader::Flac(_) => None,
+            #[cfg(feature = "symphonia")]
+            AudioReader::Lossy(_) => None,
+        }
+    }
+
+    pub fn duration(&self) -> usize {
+        match self {
+            AudioReader::Wave(reader) => reader.duration(),
+            AudioReader::Aiff(reader) => reader.duration(),
+            #[cfg(feature = "flac")]
+            AudioReader::Flac(reader) => reader.duration(),
+            #[cfg(feature = "symphonia")]
+            AudioReader::Lossy(reader) => reader.duration(),
+        }
+    }
+
+    pub fn read_frames(&mut self, frames: usize) -> Result<Vec<Vec<f64>>> {
+        match self {
+            AudioReader::Wave(reader) => reader.read_frames(frames),
+            AudioReader::Aiff(reader) => reader.read_frames(frames),
+            #[cfg(feature = "flac")]
+            AudioReader::Flac(reader) => reader.read_frames(frames),
+            #[cfg(feature = "symphonia")]
+            AudioReader::Lossy(reader) => reader.read_frames(frames),
+        }
+    }
+}
+
+/// Writer of linear PCM or IEEE float in any supported format.
+#[derive(Debug)]
+pub enum AudioWriter<W: Write + Seek> {
+    Wave(WaveWriter<W>),
+    Aiff(AiffWriter<W>),
+    #[cfg(feature = "flac")]
+    Flac(FlacWriter<W>),
+}
+
+impl<W: Write + Seek> AudioWriter<W> {
+    pub fn new(format: FileFormat, writer: W, spec: WaveSpec) -> Result<Self> {
+        match format {
+            FileFormat::Wave => Ok(AudioWriter::Wave(WaveWriter::new(writer, spec)?)),
+            FileFormat::Aiff => Ok(AudioWriter::Aiff(AiffWriter::new(writer, spec)?)),
+            #[cfg(feature = "flac")]
+            FileFormat::Flac => Ok(AudioWriter::Flac(FlacWriter::new(writer, spec)?)),
+            #[cfg(not(feature = "flac"))]
+            FileFormat::Flac => Err(crate::error::Error::DisabledFormat("FLAC", "flac")),
+            FileFormat::Lossy => Err(crate::error::Error::ReadOnlyFormat(
+                "MP3, AAC, Ogg and Matroska",
+            )),
+        }
+    }
+
+    pub fn with_dither(self, config: DitherConfig) -> Self {
+        match self {
+            AudioWriter::Wave(writer) => AudioWriter::Wave(writer.with_dither(config)),
+            AudioWriter::Aiff(writer) => AudioWriter::Aiff(writer.with_dither(config)),
+            #[cfg(feature = "flac")]
+            AudioWriter::Flac(writer) => AudioWriter::Flac(writer.with_dither(config)),
+        }
+    }
+
+    /// Chunks written after samples, which are dropped for other formats than WAVE.
+    pub fn with_metadata(self, metadata: Vec<MetadataChunk>) -> Self {
+        match self {
+            AudioWriter::Wave(writer) => AudioWriter::Wave(writer.with_metadata(metadata)),
+            writer => writer,
+        }
+    }
+
+    pub fn spec(&self) -> &WaveSpec {
+        match self {
+            AudioWriter::Wave(writer) => writer.spec(),
+            AudioWriter::Aiff(writer) => writer.spec(),
+            #[cfg(feature = "flac")]
+            AudioWriter::Flac(writer) => writer.spec(),
+        }
+    }
+
+    pub fn write_frames<T: AsRef<[f64]>>(&mut self, buffer: &[T]) -> Result<()> {
+        match self {
+            AudioWriter::Wave(writer) => writer.write_frames(buffer),
+            AudioWriter::Aiff(writer) => writer.write_frames(buffer),
+            #[cfg(feature = "flac")]
+            AudioWriter::Flac(writer) => writer.write_frames(buffer),
+        }
+    }
+
+    pub fn finalize(self) -> Result<()> {
+        match self {
+            AudioWriter::Wave(writer) => writer.finalize(),
+            AudioWriter::Aiff(writer) => writer.finalize(),
+            #[cfg(feature = "flac")]
+            AudioWriter::Flac(writer) => writer.finalize(),
+        }
+    }
+}
+
+/// Format given by the magic at the current position, which is kept.
+pub fn detect_format<R: Read + Seek>(reader: &mut R) -> Result<Option<FileFormat>> {
+    let position = reader.stream_position()?;
+    let mut header = Vec::with_capacity(12);
+    reader.take(12).read_to_end(&mut header)?;
+    reader.seek(SeekFrom::Start(position))?;
+
+    Ok(FileFormat::detect(&header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use std::io::Cursor;
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(
+            Some(FileFormat::Wave),
+            FileFormat::detect(b"RIFF\x00\x00\x00\x00WAVE")
+        );
+        assert_eq!(
+            Some(FileFormat::Aiff),
+            FileFormat::detect(b"FORM\x00\x00\x00\x00AIFC")
+        );
+        assert_eq!(None, FileFormat::detect(b"FORM\x00\x00\x00\x008SVX"));
+        assert_eq!(None, FileFormat::detect(b"RIFF"));
+        assert_eq!(
+            Some(FileFormat::Aiff),
+            FileFormat::from_extension("take 1.AIF")
+        );
+        assert_eq!(Some(FileFormat::Flac), FileFormat::detect(b"fLaC\x80"));
+        assert_eq!(None, FileFormat::from_extension("output"));
+        assert_eq!(Some(FileFormat::Lossy), FileFormat::detect(b"ID3\x04"));
+        assert_eq!(Some(FileFormat::Lossy), FileFormat::detect(b"\xff\xfb\x90"));
+        assert_eq!(
+            Some(FileFormat::Lossy),
+            FileFormat::detect(b"\x00\x00\x00\x20ftypM4A ")
+        );
+        assert_eq!(
+            Some(FileFormat::Lossy),
+            FileFormat::from_extension("a.opus")
+        );
+
+        // the same samples are read from both formats
+        let spec = WaveSpec {
+            channels: 1,
+            sample_rate: 48000,
+            sample_format: crate::normalize::SampleFormat::Int,
+            bits_per_sample: 16,
+        };
+        let samples = vec![vec![0.25, -0.5, 0.75]];
+        for format in [FileFormat::Wave, FileFormat::Aiff] {
+            let mut buf = Cursor::new(Vec::new());
+            let mut writer = AudioWriter::new(format, &mut buf, spec).unwrap();
+            writer.write_frames(&samples).unwrap();
+            writer.finalize().unwrap();
+
+            let mut reader = AudioReader::new(Cursor::new(buf.into_inner())).unwrap();
+            assert_eq!(format, reader.file_format());
+            assert_eq!(spec, reader.spec());
+            assert_eq!(samples, reader.read_frames(10).unwrap());
+        }
+    }
+
+    #[test]
+    fn read_lossy_files_only() {
+        let result = AudioReader::new(Cursor::new(b"OggS\x00\x02broken page".to_vec()));
+        #[cfg(feature = "symphonia")]
+        assert!(matches!(result, Err(Error::Symphonia(_))));
+        #[cfg(not(feature = "symphonia"))]
+        assert!(matches!(result, Err(Error::DisabledFormat(_, "symphonia"))));
+
+        let spec = WaveSpec {
+            channels: 2,
+            sample_rate: 44100,
+            sample_format: crate::normalize::SampleFormat::Float,
+            bits_per_sample: 32,
+        };
+        assert!(matches!(
+            AudioWriter::new(FileFormat::Lossy, Cursor::new(Vec::new()), spec),
+            Err(Error::ReadOnlyFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::spec::Codec;
+
+/// Companding law of G.711.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Law {
+    ALaw,
+    MuLaw,
+}
+
+impl Law {
+    pub fn from_codec(codec: Codec) -> Option<Self> {
+        match codec {
+            Codec::ALaw => Some(Law::ALaw),
+            Codec::MuLaw => Some(Law::MuLaw),
+            _ => None,
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        match self {
+            Law::ALaw => Codec::ALaw,
+            Law::MuLaw => Codec::MuLaw,
+        }
+    }
+
+    pub fn decode(&self, value: u8) -> i16 {
+        match self {
+            Law::ALaw => a_law_to_linear(value),
+            Law::MuLaw => mu_law_to_linear(value),
+        }
+    }
+
+    pub fn encode(&self, sample: i16) -> u8 {
+        match self {
+            Law::ALaw => linear_to_a_law(sample),
+            Law::MuLaw => linear_to_mu_law(sample),
+        }
+    }
+}
+
+/// Bias added to the magnitude of 14-bit sample before mu-law encoding.
+const MU_LAW_BIAS: i32 = 0x21;
+
+/// Segment of the magnitude, each one doubles `first_size`, and 8 for values out of range.
+fn segment(value: i32, first_size: i32) -> i32 {
+    (0..8)
+        .find(|segment| value < first_size << segment)
+        .unwrap_or(8)
+}
+
+pub fn linear_to_mu_law(sample: i16) -> u8 {
+    // 14 bits
+    let value = sample as i32 >> 2;
+    let (magnitude, mask) = if value < 0 {
+        (-value, 0x7F)
+    } else {
+        (value, 0xFF)
+    };
+    let magnitude = magnitude.min(8159) + MU_LAW_BIAS;
+
+    let segment = segment(magnitude, 0x40);
+    let code = if segment >= 8 {
+        0x7F
+    } else {
+        (segment << 4) | ((magnitude >> (segment + 1)) & 0x0F)
+    };
+
+    (code ^ mask) as u8
+}
+
+pub fn mu_law_to_linear(value: u8) -> i16 {
+    let value = !value as i32;
+    let magnitude = ((((value & 0x0F) << 3) + (MU_LAW_BIAS << 2)) << ((value & 0x70) >> 4))
+        - (MU_LAW_BIAS << 2);
+
+    if value & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+pub fn linear_to_a_law(sample: i16) -> u8 {
+    // 13 bits
+    let value = sample as i32 >> 3;
+    let (magnitude, mask) = if value >= 0 {
+        (value, 0xD5)
+    } else {
+        (-value - 1, 0x55)
+    };
+
+    let segment = segment(magnitude, 0x20);
+    let code = if segment >= 8 {
+        0x7F
+    } else if segment < 2 {
+        (segment << 4) | ((magnitude >> 1) & 0x0F)
+    } else {
+        (segment << 4) | ((magnitude >> segment) & 0x0F)
+    };
+
+    (code ^ mask) as u8
+}
+
+pub fn a_law_to_linear(value: u8) -> i16 {
+    let value = (value ^ 0x55) as i32;
+    let mantissa = (value & 0x0F) << 4;
+    let magnitude = match (value & 0x70) >> 4 {
+        0 => mantissa + 8,
+        1 => mantissa + 0x108,
+        segment => (mantissa + 0x108) << (segment - 1),
+    };
+
+    if value & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn companding_round_trip() {
+        for law in [Law::ALaw, Law::MuLaw] {
+            // every code survives decoding and encoding again, except negative zero of mu-law
+            for value in 0..=255_u8 {
+                let decoded = law.decode(value);
+                if !(law == Law::MuLaw && value == 0x7F) {
+                    assert_eq!(value, law.encode(decoded));
+                }
+            }
+        }
+        assert_eq!(0xFF, linear_to_mu_law(0));
+        assert_eq!(0xD5, linear_to_a_law(0));
+        assert_eq!(-32124, mu_law_to_linear(0x00));
+        assert_eq!(32256, a_law_to_linear(0xAA));
+    }
+}
@@ -17,12 +17,12 @@ impl Eq for MaxHeap {}
 
 impl Ord for MaxHeap {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(&other).unwrap()
+        self.magnitude.partial_cmp(&other.magnitude).unwrap()
     }
 }
 
 impl PartialOrd for MaxHeap {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.magnitude.partial_cmp(&other.magnitude)
+        Some(self.cmp(other))
     }
 }
@@ -0,0 +1,209 @@
+/// Quantization step for each step index.
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// Change of step index for each nibble.
+const INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+/// Bytes of a block for each channel which `encode` uses.
+pub const BLOCK_ALIGN_PER_CHANNEL: usize = 512;
+
+/// Parameters of IMA ADPCM from `fmt ` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImaAdpcmFormat {
+    pub channels: usize,
+    pub block_align: usize,
+    pub samples_per_block: usize,
+}
+
+impl ImaAdpcmFormat {
+    /// A sample of the header and two for each byte of the rest.
+    pub fn new(channels: usize, block_align: usize) -> Self {
+        ImaAdpcmFormat {
+            channels,
+            block_align,
+            samples_per_block: block_align.saturating_sub(4 * channels) * 2 / channels.max(1) + 1,
+        }
+    }
+
+    /// Read the extra fields following the size of extension in `fmt ` chunk.
+    pub fn from_extra_fields(channels: usize, block_align: usize, extra: &[u8]) -> Self {
+        let format = ImaAdpcmFormat::new(channels, block_align);
+        match extra.get(0..2) {
+            Some(&[a, b]) if u16::from_le_bytes([a, b]) != 0 => ImaAdpcmFormat {
+                samples_per_block: u16::from_le_bytes([a, b]) as usize,
+                ..format
+            },
+            _ => format,
+        }
+    }
+}
+
+/// State of a channel.
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    predictor: i32,
+    step_index: i32,
+}
+
+impl Channel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.step_index as usize];
+        let mut difference = step >> 3;
+        if nibble & 1 != 0 {
+            difference += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            difference += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            difference += step;
+        }
+        if nibble & 8 != 0 {
+            difference = -difference;
+        }
+
+        self.predictor = (self.predictor + difference).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.step_index = (self.step_index + INDEX_TABLE[nibble as usize]).clamp(0, 88);
+
+        self.predictor as i16
+    }
+
+    fn encode(&mut self, sample: i16) -> u8 {
+        let mut step = STEP_TABLE[self.step_index as usize];
+        let mut difference = sample as i32 - self.predictor;
+        let mut nibble = 0;
+        if difference < 0 {
+            nibble = 8;
+            difference = -difference;
+        }
+        for bit in [4, 2, 1] {
+            if difference >= step {
+                nibble |= bit;
+                difference -= step;
+            }
+            step >>= 1;
+        }
+        // Follow the decoder to avoid drift.
+        self.decode(nibble);
+
+        nibble
+    }
+}
+
+/// Decode a block into interleaved samples.
+fn decode_block(block: &[u8], format: &ImaAdpcmFormat, samples: &mut Vec<i16>) {
+    let channels = format.channels;
+    if channels == 0 || block.len() < 4 * channels {
+        return;
+    }
+
+    // Block header has the first sample and step index for each channel.
+    let mut states: Vec<Channel> = (0..channels)
+        .map(|c| Channel {
+            predictor: i16::from_le_bytes([block[4 * c], block[4 * c + 1]]) as i32,
+            step_index: (block[4 * c + 2] as i32).clamp(0, 88),
+        })
+        .collect();
+    let start = samples.len();
+    let frames = format.samples_per_block.max(1);
+    samples.resize(start + frames * channels, 0);
+    for (c, state) in states.iter().enumerate() {
+        samples[start + c] = state.predictor as i16;
+    }
+
+    // Each channel takes turns to have 4 bytes, that is 8 samples, low nibble first.
+    let mut decoded = vec![1; channels];
+    for (i, word) in block[4 * channels..].chunks(4).enumerate() {
+        let c = i % channels;
+        for nibble in word.iter().flat_map(|byte| [byte & 0x0f, byte >> 4]) {
+            if decoded[c] >= frames {
+                break;
+            }
+            samples[start + decoded[c] * channels + c] = states[c].decode(nibble);
+            decoded[c] += 1;
+        }
+    }
+    // Drop frames which a truncated block does not have.
+    let complete = decoded.iter().cloned().min().unwrap_or(0);
+    samples.truncate(start + complete * channels);
+}
+
+/// Decode IMA ADPCM data into interleaved 16-bit samples.
+/// `sample_frames` limits the number of frames, it is given by `fact` chunk.
+pub fn decode(data: &[u8], format: &ImaAdpcmFormat, sample_frames: Option<usize>) -> Vec<i16> {
+    let mut samples = Vec::new();
+    if format.block_align == 0 {
+        return samples;
+    }
+    for block in data.chunks(format.block_align) {
+        decode_block(block, format, &mut samples);
+    }
+    if let Some(sample_frames) = sample_frames {
+        samples.truncate(sample_frames * format.channels);
+    }
+
+    samples
+}
+
+/// Encode interleaved 16-bit samples into IMA ADPCM blocks, the last one is padded with silence.
+pub fn encode(samples: &[i16], format: &ImaAdpcmFormat) -> Vec<u8> {
+    let channels = format.channels;
+    let frames_per_block = format.samples_per_block;
+    let mut states = vec![Channel::default(); channels];
+    let mut data = Vec::new();
+
+    for block in samples.chunks(frames_per_block * channels) {
+        let sample = |frame: usize, c: usize| block.get(frame * channels + c).cloned().unwrap_or(0);
+
+        // The first sample is stored as it is, step index continues from the last block.
+        for (c, state) in states.iter_mut().enumerate() {
+            state.predictor = sample(0, c) as i32;
+            data.extend_from_slice(&sample(0, c).to_le_bytes());
+            data.push(state.step_index as u8);
+            data.push(0);
+        }
+
+        for group in (1..frames_per_block).step_by(8) {
+            for (c, state) in states.iter_mut().enumerate() {
+                let nibbles: Vec<u8> = (group..group + 8)
+                    .map(|frame| state.encode(sample(frame, c)))
+                    .collect();
+                data.extend(nibbles.chunks(2).map(|pair| pair[0] | (pair[1] << 4)));
+            }
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn encode_decode() {
+        let format = ImaAdpcmFormat::new(2, 2 * BLOCK_ALIGN_PER_CHANNEL);
+        assert_eq!(1017, format.samples_per_block);
+        let samples: Vec<i16> = (0..3000)
+            .flat_map(|i| {
+                let x = (i as f64 * 0.02).sin() * 10000.0;
+                [x as i16, -x as i16]
+            })
+            .collect();
+
+        let data = encode(&samples, &format);
+        assert_eq!(0, data.len() % format.block_align);
+        let decoded = decode(&data, &format, Some(3000));
+        assert_eq!(samples.len(), decoded.len());
+        assert_eq!(&samples[0..2], &decoded[0..2]);
+        // after the step size adapts
+        for (a, b) in samples.iter().zip(decoded.iter()).skip(2 * 32) {
+            assert!((*a as i32 - *b as i32).abs() < 400);
+        }
+    }
+}
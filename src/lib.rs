@@ -0,0 +1,34 @@
+pub mod aiff;
+pub mod dither;
+pub mod error;
+pub mod fft;
+#[cfg(feature = "flac")]
+pub mod flac;
+pub mod format;
+pub mod g711;
+pub mod heap;
+pub mod ima_adpcm;
+#[cfg(feature = "symphonia")]
+pub mod lossy;
+pub mod metadata;
+pub mod ms_adpcm;
+pub mod normalize;
+pub mod peak;
+pub mod process;
+pub mod raw;
+pub mod read;
+pub mod reader;
+pub mod riff;
+pub mod sampler;
+pub mod spec;
+pub mod stream;
+pub mod vocoder;
+pub mod write;
+pub mod writer;
+
+pub use error::{Error, Result};
+pub use reader::WaveReader;
+pub use spec::{ChannelLayout, Codec, WaveSpec};
+pub use stream::StreamingVocoder;
+pub use vocoder::{ChannelLink, Mode, PhaseGradientVocoder, Scheme, VocoderConfig, VocoderError};
+pub use writer::WaveWriter;
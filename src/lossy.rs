@@ -0,0 +1,219 @@
+use std::io::{Cursor, ErrorKind, Read};
+
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal};
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::error::{Error, Result};
+use crate::normalize::SampleFormat;
+use crate::spec::WaveSpec;
+
+/// Reader of MP3, AAC, Ogg Vorbis and Matroska which decodes packets as they are needed.
+/// Encoded bytes of the source are kept in memory, decoded samples are not.
+pub struct LossyReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    spec: WaveSpec,
+    duration: usize,
+    /// decoded samples of each channel which are not read yet
+    pending: Vec<Vec<f64>>,
+    /// whether the last packet is decoded
+    end: bool,
+}
+
+impl std::fmt::Debug for LossyReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LossyReader")
+            .field("track_id", &self.track_id)
+            .field("spec", &self.spec)
+            .field("duration", &self.duration)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LossyReader {
+    /// Probe the container and decode the first packet, which gives the format of samples.
+    pub fn new<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let source = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+        // encoder delay and padding are trimmed
+        let options = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            source,
+            &options,
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .cloned()
+            .ok_or(Error::MissingTrack)?;
+        // symphonia has no decoder of Opus yet
+        if track.codec_params.codec == CODEC_TYPE_OPUS {
+            return Err(Error::UnsupportedLossyCodec("Opus"));
+        }
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+        let mut reader = LossyReader {
+            track_id: track.id,
+            duration: track.codec_params.n_frames.unwrap_or(0) as usize,
+            format,
+            decoder,
+            // format of samples is replaced with the one of the first decoded packet
+            spec: WaveSpec {
+                channels: track
+                    .codec_params
+                    .channels
+                    .map_or(0, |channels| channels.count()),
+                sample_rate: track.codec_params.sample_rate.unwrap_or(0) as usize,
+                sample_format: SampleFormat::Float,
+                bits_per_sample: 32,
+            },
+            pending: Vec::new(),
+            end: false,
+        };
+        while !reader.end && reader.pending.is_empty() {
+            reader.decode_packet()?;
+        }
+
+        Ok(reader)
+    }
+
+    /// Format of samples the decoder gives, which is 32-bit float for lossy codecs.
+    pub fn spec(&self) -> WaveSpec {
+        self.spec
+    }
+
+    /// Number of frames of the whole stream, which is 0 if it is unknown.
+    pub fn duration(&self) -> usize {
+        self.duration
+    }
+
+    /// Decode the next packet of the track into `pending`, broken packets are skipped.
+    fn decode_packet(&mut self) -> Result<()> {
+        let packet = match self.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(error)) if error.kind() == ErrorKind::UnexpectedEof => {
+                self.end = true;
+                return Ok(());
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if packet.track_id() != self.track_id {
+            return Ok(());
+        }
+        let decoded = match self.decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        let channels = decoded.spec().channels.count();
+        if self.pending.is_empty() {
+            let (sample_format, bits_per_sample) = sample_format_of(&decoded);
+            self.spec = WaveSpec {
+                channels,
+                sample_rate: decoded.spec().rate as usize,
+                sample_format,
+                bits_per_sample,
+            };
+            self.pending = vec![Vec::new(); channels];
+        } else if channels != self.spec.channels {
+            return Err(Error::ChannelMismatch {
+                expected: self.spec.channels,
+                found: channels,
+            });
+        }
+        let mut buffer: AudioBuffer<f64> = decoded.make_equivalent();
+        decoded.convert(&mut buffer);
+        for (channel, pending) in self.pending.iter_mut().enumerate() {
+            pending.extend_from_slice(buffer.chan(channel));
+        }
+
+        Ok(())
+    }
+
+    /// Read at most `frames` frames as samples of each channel, they are empty at the end.
+    pub fn read_frames(&mut self, frames: usize) -> Result<Vec<Vec<f64>>> {
+        while !self.end && self.pending.first().map_or(0, Vec::len) < frames {
+            self.decode_packet()?;
+        }
+        let frames = frames.min(self.pending.first().map_or(0, Vec::len));
+
+        Ok(self
+            .pending
+            .iter_mut()
+            .map(|channel| channel.drain(..frames).collect())
+            .collect())
+    }
+}
+
+/// Format and bits of linear PCM or IEEE float which decoded samples fit in.
+fn sample_format_of(decoded: &AudioBufferRef) -> (SampleFormat, usize) {
+    match decoded {
+        AudioBufferRef::U8(_) | AudioBufferRef::S8(_) => (SampleFormat::Int, 8),
+        AudioBufferRef::U16(_) | AudioBufferRef::S16(_) => (SampleFormat::Int, 16),
+        AudioBufferRef::U24(_) | AudioBufferRef::S24(_) => (SampleFormat::Int, 24),
+        AudioBufferRef::U32(_) | AudioBufferRef::S32(_) => (SampleFormat::Int, 32),
+        AudioBufferRef::F32(_) => (SampleFormat::Float, 32),
+        AudioBufferRef::F64(_) => (SampleFormat::Float, 64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::checksum::Crc32;
+    use symphonia::core::io::Monitor;
+
+    /// Ogg page of a packet of the logical stream 1.
+    fn ogg_page(header_type: u8, sequence: u32, granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\x00".to_vec();
+        page.push(header_type);
+        page.extend(granule.to_le_bytes());
+        page.extend(1_u32.to_le_bytes());
+        page.extend(sequence.to_le_bytes());
+        page.extend(0_u32.to_le_bytes());
+        page.extend([1, packet.len() as u8]);
+        page.extend(packet);
+        let mut crc = Crc32::new(0);
+        crc.process_buf_bytes(&page);
+        page[22..26].copy_from_slice(&crc.crc().to_le_bytes());
+
+        page
+    }
+
+    #[test]
+    fn reject_ogg_opus() {
+        // identification header of stereo Opus at 48000 Hz
+        let mut opus_head = b"OpusHead\x01\x02".to_vec();
+        opus_head.extend(312_u16.to_le_bytes());
+        opus_head.extend(48000_u32.to_le_bytes());
+        opus_head.extend([0, 0, 0]);
+        let mut ogg = ogg_page(0x02, 0, 0, &opus_head);
+        ogg.extend(ogg_page(
+            0,
+            1,
+            0,
+            b"OpusTags\x00\x00\x00\x00\x00\x00\x00\x00",
+        ));
+        // a packet of 20 ms silence
+        ogg.extend(ogg_page(0x04, 2, 960, &[0xF8, 0xFF, 0xFE]));
+
+        assert!(matches!(
+            LossyReader::new(ogg.as_slice()),
+            Err(Error::UnsupportedLossyCodec("Opus"))
+        ));
+    }
+}
@@ -1,379 +1,316 @@
-pub mod command;
-pub mod fft;
-pub mod heap;
-pub mod normalize;
-pub mod process;
-pub mod read;
-pub mod write;
+mod command;
 
 use clap::Parser;
-use rand::Rng;
-use std::collections::BinaryHeap;
-use std::f64::consts::PI;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, Write},
+};
 
 use command::Args;
-use fft::fft;
-use heap::MaxHeap;
-use process::{hanning_window, interpolation, principal_argument};
-use read::{wav_read, WaveResult};
-use write::wav_write;
-
-fn main() -> WaveResult<()> {
-    // Get settings from cli
-    let args = Args::parse();
-
-    let mode = args.mode;
-    let ratio = args.ratio;
-    let input_path = args
-        .i
-        .unwrap_or("./10s_Hyper Bass (feat. Yunomi).wav".to_string());
-    let output_path = args.o.unwrap_or("./output.wav".to_string());
-    let buffer_size = args.buffer.unwrap_or(4096);
-    let wave_size_ratio = if mode == command::Mode::TimeStretch {
-        ratio
-    } else {
-        1.0
-    };
-
-    let source = wav_read(&input_path)?;
-    let input: Vec<f64> = source.normalized_sample_data;
-    let input_len = input.len();
-
-    // Scalar variables
-    let frame_size = buffer_size;
-    let fft_size = 2 * frame_size;
-    let synthesis_hopsize = frame_size as f64 / 4.0;
-    let analysis_hopsize = (synthesis_hopsize / ratio).round();
-    let analysis_frequency_step = input_len as f64 / fft_size as f64;
-    let scalling_factor = synthesis_hopsize / analysis_hopsize;
-    let synthesis_frequency_step = scalling_factor * analysis_frequency_step;
-    let number_of_frame = input_len / analysis_hopsize as usize;
-    let mut offset = 0;
-    let mut alter_offset = 0;
-
-    // Buffer variables to store for result of calculation
-    let mut result_buffer: Vec<f64> = vec![0.0; (input_len as f64 * wave_size_ratio) as usize];
-    let mut x_real: Vec<f64> = vec![0.0; fft_size];
-    let mut x_imag: Vec<f64> = vec![0.0; fft_size];
-    let mut y_real: Vec<f64> = vec![0.0; fft_size];
-    let mut y_imag: Vec<f64> = vec![0.0; fft_size];
-
-    // Buffer variables for polar form of complex numbers
-    let mut magnitude: Vec<Vec<f64>> = vec![vec![0.0; fft_size]; number_of_frame];
-    let mut phase: Vec<Vec<f64>> = vec![vec![0.0; fft_size]; number_of_frame];
-
-    // Hann window
-    let analysis_window = hanning_window(frame_size);
+use phase_gradient_vocoder::dither::{Dither, DitherConfig, NoiseShaping};
+use phase_gradient_vocoder::format::{AudioReader, AudioWriter, FileFormat};
+use phase_gradient_vocoder::g711::Law;
+use phase_gradient_vocoder::metadata::{remap_chunks, MetadataChunk};
+use phase_gradient_vocoder::peak::{PeakConfig, PeakMode, PeakProcessor};
+use phase_gradient_vocoder::raw::{RawFormat, RawReader, RawWriter};
+use phase_gradient_vocoder::reader::WaveReader;
+use phase_gradient_vocoder::sampler::{
+    sample_loops, stretch_loops_circularly, LoopStretch, SampleLoop,
+};
+use phase_gradient_vocoder::write::{stream_wave_header, wav_write_g711, wav_write_ima_adpcm};
+use phase_gradient_vocoder::{ChannelLink, PhaseGradientVocoder, Scheme, VocoderConfig, WaveSpec};
+
+/// Path of stdin and stdout.
+const STDIO: &str = "-";
+
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Source of input samples.
+enum Input {
+    /// file with a header, stdin of other formats than wave is read into memory
+    File(AudioReader<Box<dyn ReadSeek>>),
+    /// wave stream read forward only
+    Wave(Box<WaveReader<Box<dyn Read>>>),
+    /// headerless samples read block by block
+    Raw(RawReader<Box<dyn Read>>),
+}
 
-    // Preprocessing for the phase gradiation due to it needs the last two and the next one frames.
-    for i in 0..number_of_frame {
-        offset = analysis_hopsize as usize * i;
+impl Input {
+    fn spec(&self) -> WaveSpec {
+        match self {
+            Input::File(reader) => reader.spec(),
+            Input::Wave(reader) => reader.spec(),
+            Input::Raw(reader) => reader.spec(),
+        }
+    }
 
-        // Zero padding
-        x_real.fill(0.0);
-        x_imag.fill(0.0);
-        // Windowning real signal
-        for j in 0..frame_size {
-            if offset + j >= input_len {
-                break;
-            } else {
-                x_real[j] = input[offset + j] * analysis_window[j];
-            }
+    /// Number of frames, which is unknown for headerless samples.
+    fn duration(&self) -> usize {
+        match self {
+            Input::File(reader) => reader.duration(),
+            Input::Wave(reader) => reader.duration(),
+            Input::Raw(_) => 0,
         }
-        // Shift signal to center
-        x_real.rotate_right(frame_size);
-        // FFT
-        fft(&mut x_real, &mut x_imag, fft_size, false);
+    }
 
-        // In its essence, the method proceeds by pro-cessing one frame at a time computing the synthesis phase of the current n-th frame φs(·,n).
-        // It requires storing the already computed phase φs(·,n −1) and the time derivative (∆tφa) (·,n−1) of the previous (n−1)-th frame and further,
-        // it requires access to the coefficients of the previous, current and one "future" frame (c(·,n−1), c(·,n) and c(·,n+1)) assuming the centered differentiation scheme
-        for j in 0..fft_size {
-            magnitude[i][j] = (x_real[j] * x_real[j] + x_imag[j] * x_imag[j]).sqrt();
-            phase[i][j] = x_imag[j].atan2(x_real[j]);
+    fn metadata(&self) -> &[MetadataChunk] {
+        match self {
+            Input::File(reader) => reader.metadata(),
+            Input::Wave(reader) => reader.metadata(),
+            Input::Raw(_) => &[],
         }
     }
 
-    // Buffer variables for calculating frames
-    let mut alter_phase: Vec<Vec<f64>> = vec![vec![0.0; fft_size]; number_of_frame];
-    let omega: Vec<f64> = vec![0.0; fft_size]
-        .iter()
-        .enumerate()
-        .map(|(i, _)| ((2.0 * PI) * analysis_hopsize * i as f64) / fft_size as f64)
-        .collect();
-    let mut time_delta_phi: Vec<Vec<f64>> = vec![vec![0.0; fft_size]; number_of_frame];
-    let mut frequency_delta_phi: Vec<Vec<f64>> = vec![vec![0.0; fft_size]; number_of_frame];
-    let mut frequency_forward_delta_phi: Vec<Vec<f64>> = vec![vec![0.0; fft_size]; number_of_frame];
-    let mut frequency_backward_delta_phi = vec![vec![0.0; fft_size]; number_of_frame];
+    fn companding_law(&self) -> Option<Law> {
+        match self {
+            Input::File(reader) => reader.companding_law(),
+            Input::Wave(reader) => reader.format().companding_law,
+            Input::Raw(_) => None,
+        }
+    }
 
-    // Variables for phase gradiation
-    let relative_tolerance = 10.0_f64.powi(-6);
-    let mut max_heap: BinaryHeap<MaxHeap> = BinaryHeap::new();
-    let mut rng = rand::thread_rng();
+    fn read_frames(&mut self, frames: usize) -> phase_gradient_vocoder::Result<Vec<Vec<f64>>> {
+        match self {
+            Input::File(reader) => reader.read_frames(frames),
+            Input::Wave(reader) => reader.read_frames(frames),
+            Input::Raw(reader) => reader.read_frames(frames),
+        }
+    }
+}
 
-    for i in 0..number_of_frame {
-        // Determine the ratio that time-stretch needs synthesis hop size to strech buffer.
-        alter_offset = if mode == command::Mode::TimeStretch {
-            synthesis_hopsize as usize * i
-        } else {
-            analysis_hopsize as usize * i
-        };
+/// Destination of output samples.
+enum Output {
+    /// linear PCM or IEEE float written block by block
+    Stream(Box<AudioWriter<BufWriter<File>>>),
+    /// headerless samples written block by block, which follow the header of a wave stream
+    Raw(Box<RawWriter<Box<dyn Write>>>),
+    /// samples of each channel which are compressed at last
+    Buffer(Vec<Vec<f64>>),
+}
 
-        // (∆tφa) (m,n) and (∆fφa) (m,n) are computed for all m and current n
-        for j in 0..fft_size {
-            // It cannot calculate center value, if the bin or vector of the bin placed in the edge of buffer,
-            // So it should pick the very value.
+impl Output {
+    fn write(&mut self, block: Vec<Vec<f64>>) -> phase_gradient_vocoder::Result<()> {
+        match self {
+            Output::Stream(writer) => writer.write_frames(&block),
+            Output::Raw(writer) => writer.write_frames(&block),
+            Output::Buffer(buffer) => {
+                extend_channels(buffer, block);
+                Ok(())
+            }
+        }
+    }
+}
 
-            // φs(m, n) = φs(m, n − 1) + as / 2 ((∆tφa) (m, n − 1) + (∆tφa) (m, n)) .
-            time_delta_phi[i][j] = if i as isize - 2 <= 0 || i + 1 >= number_of_frame {
-                synthesis_hopsize
-                    * ((1.0 / analysis_hopsize) * principal_argument(phase[i][j] - omega[j])
-                        + ((2.0 * PI * j as f64) / fft_size as f64))
-            } else {
-                synthesis_hopsize / 2.0
-                    * ((1.0 / analysis_hopsize
-                        * principal_argument(phase[i - 1][j] - phase[i - 2][j] - omega[j])
-                        + ((2.0 * PI * j as f64) / fft_size as f64))
-                        + (1.0 / analysis_hopsize
-                            * principal_argument(phase[i + 1][j] - phase[i][j] - omega[j])
-                            + ((2.0 * PI * j as f64) / fft_size as f64)))
-            };
+/// Append samples of each channel.
+fn extend_channels(buffer: &mut [Vec<f64>], block: Vec<Vec<f64>>) {
+    for (channel, samples) in buffer.iter_mut().zip(block) {
+        channel.extend(samples);
+    }
+}
 
-            // Centerd value when it locates on the edge of frequency.
-            frequency_delta_phi[i][j] = if j + 1 >= fft_size || j as isize - 1 < 0 {
-                synthesis_frequency_step
-                    * (1.0 / analysis_frequency_step * principal_argument(phase[i][j]))
-            } else {
-                synthesis_frequency_step / 2.0
-                    * (1.0 / analysis_frequency_step
-                        * principal_argument(phase[i][j] - phase[i][j - 1])
-                        + 1.0 / analysis_frequency_step
-                            * principal_argument(phase[i][j + 1] - phase[i][j]))
-            };
+fn main() -> Result<(), Box<dyn Error>> {
+    // Get settings from cli
+    let args = Args::parse();
 
-            // (∆f,fwdφa) (m, n) = 1 / ba [φa(m + 1, n) − φa(m, n)] 2π
-            frequency_forward_delta_phi[i][j] = if j + 2 >= fft_size || j as isize - 1 < 0 {
-                frequency_delta_phi[i][j]
-            } else {
-                synthesis_frequency_step / 2.0
-                    * (1.0 / analysis_frequency_step
-                        * principal_argument(phase[i][j] - phase[i][j - 1])
-                        + 1.0 / analysis_frequency_step
-                            * principal_argument(phase[i][j + 2] - phase[i][j + 1]))
-            };
+    let input_path = args.i;
+    let output_path = args.o.unwrap_or(if args.raw.is_some() {
+        "./output.raw".to_string()
+    } else {
+        "./output.wav".to_string()
+    });
+    // Output format follows the extension, compressed samples are written only as WAVE files
+    let file_format = FileFormat::from_extension(&output_path);
+    if (args.ima_adpcm || args.g711) && output_path == STDIO {
+        return Err("compressed samples are not written to stdout".into());
+    }
+    if (args.ima_adpcm || args.g711) && file_format.is_some_and(|format| format != FileFormat::Wave)
+    {
+        return Err("compressed samples are written only in wave files".into());
+    }
+    // headerless samples under the name of a format with a header would be mistaken for it
+    if args.raw.is_some() && file_format.is_some() {
+        return Err(
+            "headerless samples need an output path without the extension of an audio format"
+                .into(),
+        );
+    }
+    let file_format = file_format.unwrap_or(FileFormat::Wave);
+    let config = VocoderConfig {
+        scheme: args.scheme.unwrap_or(Scheme::Centered),
+        link: args.link.unwrap_or(ChannelLink::Independent),
+        seed: args.seed,
+        ..VocoderConfig::new(args.mode, args.ratio).with_frame_size(args.buffer.unwrap_or(4096))
+    };
+    let vocoder = PhaseGradientVocoder::new(config)?;
 
-            // (∆f,backφa) (m, n) = 1 / ba [φa(m, n) − φa(m − 1, n)] 2π
-            frequency_backward_delta_phi[i][j] = if j + 1 >= fft_size || j as isize - 2 < 0 {
-                frequency_delta_phi[i][j]
+    // Headerless samples are read and written in the same format
+    let mut source = match (args.raw, input_path.as_str()) {
+        (Some(format), path) => {
+            let reader: Box<dyn Read> = if path == STDIO {
+                Box::new(io::stdin().lock())
             } else {
-                synthesis_frequency_step / 2.0
-                    * (1.0 / analysis_frequency_step
-                        * principal_argument(phase[i][j - 1] - phase[i][j - 2])
-                        + 1.0 / analysis_frequency_step
-                            * principal_argument(phase[i][j + 1] - phase[i][j]))
+                Box::new(BufReader::new(File::open(path)?))
             };
+            let channels = args
+                .channels
+                .ok_or("--channels is needed for raw samples")?;
+            let sample_rate = args.rate.ok_or("--rate is needed for raw samples")?;
+            Input::Raw(RawReader::new(reader, format, channels, sample_rate)?)
         }
-
-        // Start calculate phase gradiation.
-        (|| -> () {
-            // Return current frame's phase due to there're no the last two frame information until it's third frame.
-            if i as isize - 1 <= 0 {
-                for j in 0..fft_size {
-                    alter_phase[i][j] = time_delta_phi[i][j];
+        (None, STDIO) => {
+            // a wave stream is read forward only, and other formats are read into memory
+            let mut stdin = io::stdin().lock();
+            let mut magic = Vec::new();
+            (&mut stdin).take(12).read_to_end(&mut magic)?;
+            match FileFormat::detect(&magic) {
+                Some(FileFormat::Wave) | None => {
+                    let reader: Box<dyn Read> = Box::new(Cursor::new(magic).chain(stdin));
+                    Input::Wave(Box::new(WaveReader::from_stream(reader)?))
                 }
-                return;
-            }
-
-            // Preprocessing for heap sort.
-
-            // abstol ← tol·max(s(m,n) ∪ s(m,n - 1))
-            let absolute_tolerance = relative_tolerance
-                * f64::max(
-                    magnitude[i].clone().into_iter().fold(0.0 / 0.0, f64::max),
-                    magnitude[i - 1]
-                        .clone()
-                        .into_iter()
-                        .fold(0.0 / 0.0, f64::max),
-                );
-            // set I = { m: s(m,n) > abstol }
-            let mut frequency_indices: Vec<usize> = magnitude[i]
-                .iter()
-                .enumerate()
-                .filter(|(_, &x)| x > *&absolute_tolerance)
-                .map(|(i, _)| i)
-                .collect();
-            // Assign random values to φs(m,n) for m ∉ I
-            let phase_advance: Vec<usize> = phase[i].iter().enumerate().map(|(i, _)| i).collect();
-            let difference: Vec<usize> = phase_advance
-                .into_iter()
-                .filter(|bin| !frequency_indices.contains(bin))
-                .collect();
-            difference
-                .iter()
-                .for_each(|j| alter_phase[i][*j] = rng.gen());
-
-            // Construct a self-sorting max heap for (m,n) tuples
-            // Insert (m,n - 1) for m ∈ I into the heap
-            frequency_indices.iter().for_each(|j| {
-                max_heap.push(MaxHeap {
-                    magnitude: magnitude[i - 1][*j],
-                    frequency_index: *j,
-                    frame: i - 1,
-                })
-            });
-
-            while !frequency_indices.is_empty() {
-                while !max_heap.is_empty() {
-                    let max = max_heap.pop().unwrap();
-                    let frequency_index = max.frequency_index;
-
-                    // Propagate the phase in the time direction
-                    if max.frame == i - 1 {
-                        // (mh,n) ∈ I
-                        if frequency_indices.contains(&frequency_index) {
-                            alter_phase[i][frequency_index] = alter_phase[i - 1][frequency_index]
-                                + time_delta_phi[i][frequency_index];
-                            // Remove (mh,n) from I
-                            let set_index = frequency_indices
-                                .iter()
-                                .position(|&v| v == frequency_index)
-                                .unwrap();
-                            frequency_indices.remove(set_index);
-                            // Insert (mh,n) into the heap
-                            max_heap.push(MaxHeap {
-                                magnitude: magnitude[i][frequency_index],
-                                frequency_index: frequency_index,
-                                frame: i,
-                            });
-                        }
-                    }
-
-                    // Propagate the phase in the frequency direction
-                    if max.frame == i {
-                        // The edge of frequency for forward
-                        if frequency_index + 1 >= fft_size {
-                            alter_phase[i][frequency_index] = alter_phase[i][frequency_index]
-                                + frequency_forward_delta_phi[i][frequency_index];
-
-                            if frequency_indices.contains(&frequency_index) {
-                                // Remove (mh,n) from I
-                                let set_index = frequency_indices
-                                    .iter()
-                                    .position(|&v| v == frequency_index)
-                                    .unwrap();
-                                frequency_indices.remove(set_index);
-                                // Insert (mh,n) into the heap
-                                max_heap.push(MaxHeap {
-                                    magnitude: magnitude[i][frequency_index],
-                                    frequency_index: frequency_index,
-                                    frame: i,
-                                });
-                            }
-                            continue;
-                        }
-
-                        // The edge of frequency for backward
-                        if frequency_index as isize - 1 < 0 {
-                            alter_phase[i][frequency_index] = alter_phase[i][frequency_index]
-                                - frequency_backward_delta_phi[i][frequency_index];
-                            if frequency_indices.contains(&frequency_index) {
-                                // Remove (mh,n) from I
-                                let set_index = frequency_indices
-                                    .iter()
-                                    .position(|&v| v == frequency_index)
-                                    .unwrap();
-                                frequency_indices.remove(set_index);
-                                // Insert (mh,n) into the heap
-                                max_heap.push(MaxHeap {
-                                    magnitude: magnitude[i][frequency_index],
-                                    frequency_index: frequency_index,
-                                    frame: i,
-                                });
-                            }
-                            continue;
-                        }
-
-                        // (mh + 1,n) ∈ I
-                        if frequency_indices.contains(&(frequency_index + 1)) {
-                            alter_phase[i][frequency_index + 1] = alter_phase[i][frequency_index]
-                                + frequency_forward_delta_phi[i][frequency_index];
-                            // Remove (mh + 1,n) from I
-                            let set_index = frequency_indices
-                                .iter()
-                                .position(|&v| v == frequency_index + 1)
-                                .unwrap();
-                            frequency_indices.remove(set_index);
-                            // Insert (mh + 1,n) into the heap
-                            max_heap.push(MaxHeap {
-                                magnitude: magnitude[i][frequency_index + 1],
-                                frequency_index: frequency_index + 1,
-                                frame: i,
-                            });
-                        }
-
-                        // (mh - 1,n) ∈ I
-                        if frequency_indices.contains(&(frequency_index - 1)) {
-                            alter_phase[i][frequency_index - 1] = alter_phase[i][frequency_index]
-                                - frequency_backward_delta_phi[i][frequency_index];
-                            // Remove (mh - 1,n) from I
-                            let set_index = frequency_indices
-                                .iter()
-                                .position(|&v| v == frequency_index - 1)
-                                .unwrap();
-                            frequency_indices.remove(set_index);
-                            // Insert (mh - 1,n) into the heap
-                            max_heap.push(MaxHeap {
-                                magnitude: magnitude[i][frequency_index - 1],
-                                frequency_index: frequency_index - 1,
-                                frame: i,
-                            });
-                        }
-                    }
+                Some(_) => {
+                    stdin.read_to_end(&mut magic)?;
+                    let reader: Box<dyn ReadSeek> = Box::new(Cursor::new(magic));
+                    Input::File(AudioReader::new(reader)?)
                 }
             }
-        }());
-
-        // Resynthesis
-        for j in 0..fft_size {
-            y_real[j] = magnitude[i][j] * alter_phase[i][j].cos();
-            y_imag[j] = magnitude[i][j] * alter_phase[i][j].sin();
         }
-
-        // IFFT
-        fft(&mut y_real, &mut y_imag, fft_size, true);
-
-        // Shift signal to lead
-        y_real.rotate_left(frame_size);
-
-        // Windowning real signal
-        for j in 0..frame_size {
-            y_real[j] = y_real[j] * analysis_window[j];
+        (None, path) => {
+            let reader: Box<dyn ReadSeek> = Box::new(BufReader::new(File::open(path)?));
+            Input::File(AudioReader::new(reader)?)
         }
-
-        // Pitch-shift needs interpolation of audio signal.
-        let synthesized_buffer = if mode == command::Mode::PitchShift {
-            let interpolate_length =
-                (fft_size as f64 * analysis_hopsize / synthesis_hopsize) as usize;
-            interpolation(&fft_size, &interpolate_length, &y_real, &ratio)
+    };
+    let spec = source.spec();
+    let law = if args.g711 {
+        let law = source.companding_law();
+        Some(law.ok_or("input file is not companded with G.711")?)
+    } else {
+        None
+    };
+    // Carry metadata of the input with positions moved through the time map of the vocoder
+    let loops: Vec<SampleLoop> = source
+        .metadata()
+        .iter()
+        .filter(|chunk| &chunk.id == b"smpl")
+        .flat_map(|chunk| sample_loops(&chunk.data))
+        .collect();
+    let output_len = vocoder.output_len(source.duration());
+    let mut metadata = source.metadata().to_vec();
+    remap_chunks(&mut metadata, vocoder.wave_size_ratio(), |position| {
+        vocoder.map_position(position as usize).min(output_len) as u64
+    });
+
+    // Write file in the format of the input one unless it is compressed
+    let dither = if args.dither.is_some() || args.noise_shaping.is_some() {
+        Some(DitherConfig {
+            dither: args.dither.unwrap_or(Dither::Tpdf),
+            noise_shaping: args.noise_shaping.unwrap_or(NoiseShaping::None),
+            seed: args.seed.unwrap_or_default(),
+        })
+    } else {
+        None
+    };
+    let mut output = if args.ima_adpcm || args.g711 {
+        Output::Buffer(vec![Vec::new(); spec.channels])
+    } else if args.raw.is_some() || output_path == STDIO {
+        let mut writer: Box<dyn Write> = if output_path == STDIO {
+            Box::new(BufWriter::new(io::stdout().lock()))
         } else {
-            y_real.clone()
+            Box::new(BufWriter::new(File::create(&output_path)?))
         };
-
-        for j in 0..frame_size {
-            if alter_offset + j >= result_buffer.len() {
-                break;
+        // samples of a wave stream follow the header of unknown sizes, so metadata is dropped
+        let format = match args.raw {
+            Some(format) => format,
+            None => {
+                let format = RawFormat::from_spec(&spec).ok_or(
+                    phase_gradient_vocoder::error::Error::UnsupportedBitsPerSample(
+                        spec.bits_per_sample,
+                        spec.sample_format,
+                    ),
+                )?;
+                writer.write_all(&stream_wave_header(&spec))?;
+                format
             }
-            result_buffer[alter_offset + j] =
-                result_buffer[alter_offset + j] + synthesized_buffer[j];
+        };
+        let writer = RawWriter::new(writer, format, spec.channels);
+        Output::Raw(Box::new(match dither {
+            Some(config) => writer.with_dither(config),
+            None => writer,
+        }))
+    } else {
+        let file = BufWriter::new(File::create(&output_path)?);
+        let writer = AudioWriter::new(file_format, file, spec)?.with_metadata(metadata.clone());
+        Output::Stream(Box::new(match dither {
+            Some(config) => writer.with_dither(config),
+            None => writer,
+        }))
+    };
+
+    // Feed the vocoder and the peak processor block by block
+    let peak_config = PeakConfig::new(args.peak.unwrap_or(PeakMode::Clip), spec.sample_rate);
+    let peak_config = peak_config.with_ceiling(args.ceiling.unwrap_or(peak_config.ceiling));
+    let mut peak = PeakProcessor::new(peak_config, spec.channels);
+    let mut stream = vocoder.stream_channels(spec.channels)?;
+    // Circular loops need the whole input and output
+    let circular = args.loop_stretch == Some(LoopStretch::Circular) && !loops.is_empty();
+    let mut whole_input = vec![Vec::new(); spec.channels];
+    let mut whole_output = vec![Vec::new(); spec.channels];
+    loop {
+        let block = source.read_frames(65536)?;
+        if block.first().is_none_or(|channel| channel.is_empty()) {
+            break;
+        }
+        let stretched = stream.push_channels(&block);
+        if circular {
+            extend_channels(&mut whole_input, block);
+            extend_channels(&mut whole_output, stretched);
+        } else {
+            output.write(peak.push_channels(&stretched))?;
         }
     }
+    let stretched = stream.finish_channels();
+    if circular {
+        extend_channels(&mut whole_output, stretched);
+        stretch_loops_circularly(&vocoder, &whole_input, &mut whole_output, &loops)?;
+        output.write(peak.push_channels(&whole_output))?;
+    } else {
+        output.write(peak.push_channels(&stretched))?;
+    }
+    let (rest, report) = peak.finish_channels();
+    output.write(rest)?;
+    if report.count > 0 {
+        // only the first regions are printed
+        let timestamps: Vec<String> = report
+            .timestamps()
+            .iter()
+            .take(10)
+            .map(|seconds| format!("{:.3}s", seconds))
+            .collect();
+        let rest = report.regions.len().saturating_sub(timestamps.len());
+        eprintln!(
+            "{} samples exceeded full scale in {} regions at {}{}",
+            report.count,
+            report.regions.len(),
+            timestamps.join(", "),
+            if rest > 0 {
+                format!(" and {} more", rest)
+            } else {
+                String::new()
+            }
+        );
+    }
 
-    // Write file settings
-    let fs = source.sample_rate;
-    let bit = source.bits_per_sample;
-    let channels = 1;
-    let size = ((fs * (bit / 8) * channels * (input_len / fs)) as f64 * wave_size_ratio) as usize;
-    let result = wav_write(&output_path, result_buffer, size, fs, bit)?;
-
-    Ok(result)
+    match (output, law) {
+        (Output::Stream(writer), _) => writer.finalize()?,
+        (Output::Raw(writer), _) => writer.finalize()?,
+        (Output::Buffer(buffer), Some(law)) => {
+            wav_write_g711(&output_path, buffer, spec.sample_rate, law, &metadata)?
+        }
+        (Output::Buffer(buffer), None) => {
+            wav_write_ima_adpcm(&output_path, buffer, spec.sample_rate, &metadata)?
+        }
+    }
+    Ok(())
 }
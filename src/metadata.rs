@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::read::u32_at;
+use crate::riff::Chunks;
+use crate::sampler::remap_loops;
+
+/// Chunk other than the format and samples, which is carried from the input to the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataChunk {
+    /// four character code like `LIST` or `bext`
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl MetadataChunk {
+    pub fn new(id: [u8; 4], data: Vec<u8>) -> Self {
+        MetadataChunk { id, data }
+    }
+
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.id).to_string()
+    }
+
+    /// Chunks other than the format, samples, `fact` and `ds64` which the writer makes and padding.
+    pub fn is_metadata(id: &[u8; 4]) -> bool {
+        !matches!(
+            id,
+            b"fmt " | b"data" | b"fact" | b"ds64" | b"JUNK" | b"junk" | b"PAD " | b"FLLR"
+        )
+    }
+
+    /// Chunk header, data and the pad byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.padded_len());
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        if self.data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+
+        bytes
+    }
+
+    /// Bytes of the whole chunk.
+    pub fn padded_len(&self) -> usize {
+        8 + self.data.len() + self.data.len() % 2
+    }
+}
+
+/// Bytes of the chunks following each other.
+pub fn chunks_len(chunks: &[MetadataChunk]) -> usize {
+    chunks.iter().map(|chunk| chunk.padded_len()).sum()
+}
+
+/// Offset of the time reference in `bext` chunk, following description, originator and so on.
+const BEXT_TIME_REFERENCE: usize = 338;
+/// Bytes of a cue point.
+const CUE_POINT_SIZE: usize = 24;
+
+fn set_u32(data: &mut [u8], at: usize, value: u64) {
+    data[at..at + 4].copy_from_slice(&(value.min(u32::MAX as u64) as u32).to_le_bytes());
+}
+
+/// Offsets of cue points in `cue ` chunk data.
+fn cue_points(data: &[u8]) -> impl Iterator<Item = usize> {
+    let count = if data.len() >= 4 { u32_at(data, 0) } else { 0 };
+    (0..count.min(data.len().saturating_sub(4) / CUE_POINT_SIZE)).map(|i| 4 + i * CUE_POINT_SIZE)
+}
+
+/// Move time-referenced metadata by `map` from frames of the input to ones of the output,
+/// which are positions of `cue ` points, lengths of `ltxt` regions and loops of `smpl`.
+/// The time reference of `bext` counts samples since midnight rather than a position in the file,
+/// so it is just scaled by `ratio` of the output duration to the input one.
+pub fn remap_chunks(chunks: &mut [MetadataChunk], ratio: f64, map: impl Fn(u64) -> u64) {
+    // positions of cue points before they are moved, which regions begin at
+    let mut cue_positions = HashMap::new();
+    for chunk in chunks.iter().filter(|chunk| &chunk.id == b"cue ") {
+        for at in cue_points(&chunk.data) {
+            cue_positions.insert(u32_at(&chunk.data, at), u32_at(&chunk.data, at + 4) as u64);
+        }
+    }
+
+    for chunk in chunks.iter_mut() {
+        let data = &mut chunk.data;
+        match &chunk.id {
+            b"bext" if data.len() >= BEXT_TIME_REFERENCE + 8 => {
+                let low = u32_at(data, BEXT_TIME_REFERENCE) as u64;
+                let high = u32_at(data, BEXT_TIME_REFERENCE + 4) as u64;
+                let time_reference = ((high << 32 | low) as f64 * ratio).round() as u64;
+                set_u32(data, BEXT_TIME_REFERENCE, time_reference & 0xFFFF_FFFF);
+                set_u32(data, BEXT_TIME_REFERENCE + 4, time_reference >> 32);
+            }
+            b"cue " => {
+                for at in cue_points(data).collect::<Vec<_>>() {
+                    // position and sample offset in `data` chunk
+                    for at in [at + 4, at + 20] {
+                        let position = map(u32_at(data, at) as u64);
+                        set_u32(data, at, position);
+                    }
+                }
+            }
+            b"LIST" if data.get(0..4) == Some(b"adtl") => {
+                let regions: Vec<usize> = Chunks::new(data, 4)
+                    .filter(|sub| &sub.id == b"ltxt" && sub.offset + 8 <= data.len())
+                    .map(|sub| sub.offset)
+                    .collect();
+                for at in regions {
+                    let start = cue_positions
+                        .get(&u32_at(data, at))
+                        .copied()
+                        .unwrap_or_default();
+                    let length = u32_at(data, at + 4) as u64;
+                    set_u32(data, at + 4, map(start + length).saturating_sub(map(start)));
+                }
+            }
+            b"smpl" => remap_loops(data, &map),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn remap_time_references() {
+        let mut bext = vec![0; 602];
+        bext[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+            .copy_from_slice(&(3_u64 << 31).to_le_bytes());
+
+        let mut cue = 2_u32.to_le_bytes().to_vec();
+        for (id, position) in [(1_u32, 100_u32), (2, 1000)] {
+            cue.extend(id.to_le_bytes());
+            cue.extend(position.to_le_bytes());
+            cue.extend(b"data");
+            cue.extend([0; 8]);
+            cue.extend(position.to_le_bytes());
+        }
+
+        let mut list = b"adtl".to_vec();
+        list.extend(b"labl\x05\x00\x00\x00");
+        list.extend(1_u32.to_le_bytes());
+        list.extend(b"a\x00");
+        list.extend(b"ltxt\x14\x00\x00\x00");
+        list.extend(2_u32.to_le_bytes());
+        list.extend(300_u32.to_le_bytes());
+        list.extend(b"rgn \x00\x00\x00\x00\x00\x00\x00\x00");
+
+        let mut chunks = vec![
+            MetadataChunk::new(*b"bext", bext),
+            MetadataChunk::new(*b"cue ", cue),
+            MetadataChunk::new(*b"LIST", list),
+            MetadataChunk::new(*b"iXML", b"<BWFXML/>".to_vec()),
+        ];
+        let original = chunks.clone();
+        remap_chunks(&mut chunks, 1.5, |position| position * 3 / 2);
+
+        let bext = &chunks[0].data;
+        assert_eq!(
+            9_u64 << 30,
+            u64::from_le_bytes(
+                bext[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+                    .try_into()
+                    .unwrap()
+            )
+        );
+        let cue = &chunks[1].data;
+        assert_eq!(
+            [150, 150, 1500, 1500],
+            [
+                u32_at(cue, 8),
+                u32_at(cue, 24),
+                u32_at(cue, 32),
+                u32_at(cue, 48)
+            ]
+        );
+        // the region from 1000 to 1300
+        assert_eq!(450, u32_at(&chunks[2].data, 30));
+        assert_eq!(original[3], chunks[3]);
+        assert_eq!(original[2].data.len(), chunks[2].data.len());
+
+        assert_eq!(
+            b"iXML\x09\x00\x00\x00<BWFXML/>\x00",
+            &chunks[3].to_bytes()[..]
+        );
+        assert_eq!(18, chunks_len(&chunks[3..]));
+        assert!(!MetadataChunk::is_metadata(b"fact"));
+    }
+
+    #[test]
+    fn scale_time_reference_far_beyond_file() {
+        // 10:00:00 at 48 kHz, which is far beyond the length of the file
+        let time_reference = 10 * 3600 * 48000_u64;
+        let mut bext = vec![0; 602];
+        bext[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+            .copy_from_slice(&time_reference.to_le_bytes());
+        let mut chunks = vec![MetadataChunk::new(*b"bext", bext)];
+        // positions in the file are clamped and offset, which must not affect the time reference
+        remap_chunks(&mut chunks, 0.8, |position| (position + 4096).min(48000));
+
+        assert_eq!(
+            time_reference * 4 / 5,
+            u64::from_le_bytes(
+                chunks[0].data[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+                    .try_into()
+                    .unwrap()
+            )
+        );
+    }
+}
@@ -0,0 +1,180 @@
+/// Scale of the quantization step for each nibble.
+const ADAPTATION_TABLE: [i32; 16] = [
+    230, 230, 230, 230, 307, 409, 512, 614, 768, 614, 512, 409, 307, 230, 230, 230,
+];
+
+/// Range of the quantization step, the maximum keeps its adaptation within `i32` as ffmpeg does.
+const MIN_DELTA: i32 = 16;
+const MAX_DELTA: i32 = i32::MAX / 768;
+
+/// Predictor coefficients which every MS-ADPCM file starts with.
+pub const STANDARD_COEFFICIENTS: [(i32, i32); 7] = [
+    (256, 0),
+    (512, -256),
+    (0, 0),
+    (192, 64),
+    (240, 0),
+    (460, -208),
+    (392, -232),
+];
+
+/// Parameters of MS-ADPCM from `fmt ` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsAdpcmFormat {
+    pub channels: usize,
+    pub block_align: usize,
+    pub samples_per_block: usize,
+    pub coefficients: Vec<(i32, i32)>,
+}
+
+impl MsAdpcmFormat {
+    /// Read the extra fields following the size of extension in `fmt ` chunk.
+    pub fn from_extra_fields(channels: usize, block_align: usize, extra: &[u8]) -> Option<Self> {
+        let number = |at: usize| -> Option<i32> {
+            let bytes = extra.get(at..at + 2)?;
+            Some(i16::from_le_bytes([bytes[0], bytes[1]]) as i32)
+        };
+        // Two samples of the header and two for each byte of the rest, if it is not given.
+        let samples_per_block = match number(0)? as u16 as usize {
+            0 => block_align.saturating_sub(7 * channels) * 2 / channels.max(1) + 2,
+            n => n,
+        };
+        let number_of_coefficients = number(2)? as u16 as usize;
+        let coefficients = (0..number_of_coefficients)
+            .map(|i| Some((number(4 + i * 4)?, number(6 + i * 4)?)))
+            .collect::<Option<Vec<(i32, i32)>>>()?;
+
+        Some(MsAdpcmFormat {
+            channels,
+            block_align,
+            samples_per_block,
+            coefficients,
+        })
+    }
+}
+
+/// State of a channel in a block.
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    coefficient: (i32, i32),
+    delta: i32,
+    sample1: i32,
+    sample2: i32,
+}
+
+impl Channel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        // coefficients and samples of a hostile header may exceed `i32` when they are multiplied
+        let predictor = (self.sample1 as i64 * self.coefficient.0 as i64
+            + self.sample2 as i64 * self.coefficient.1 as i64)
+            / 256;
+        // signed 4-bit value
+        let signed = ((nibble as i64) << 60) >> 60;
+        let sample =
+            (predictor + signed * self.delta as i64).clamp(i16::MIN as i64, i16::MAX as i64) as i32;
+
+        self.sample2 = self.sample1;
+        self.sample1 = sample;
+        self.delta =
+            ((ADAPTATION_TABLE[nibble as usize] * self.delta) >> 8).clamp(MIN_DELTA, MAX_DELTA);
+
+        sample as i16
+    }
+}
+
+/// Decode a block into interleaved samples.
+fn decode_block(block: &[u8], format: &MsAdpcmFormat, samples: &mut Vec<i16>) {
+    let channels = format.channels;
+    if channels == 0 || block.len() < 7 * channels {
+        return;
+    }
+    let word = |at: usize| i16::from_le_bytes([block[at], block[at + 1]]) as i32;
+
+    // Block header has predictor indices, deltas, the second and the first samples for each channel.
+    let mut states: Vec<Channel> = (0..channels)
+        .map(|c| Channel {
+            coefficient: format
+                .coefficients
+                .get(block[c] as usize)
+                .cloned()
+                .unwrap_or(STANDARD_COEFFICIENTS[0]),
+            delta: word(channels + 2 * c),
+            sample1: word(3 * channels + 2 * c),
+            sample2: word(5 * channels + 2 * c),
+        })
+        .collect();
+    for state in states.iter() {
+        samples.push(state.sample2 as i16);
+    }
+    for state in states.iter() {
+        samples.push(state.sample1 as i16);
+    }
+
+    // Rest of frames in the block, high nibble first.
+    let remaining = format.samples_per_block.saturating_sub(2) * channels;
+    let nibbles = block[7 * channels..]
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .take(remaining);
+    for (i, nibble) in nibbles.enumerate() {
+        samples.push(states[i % channels].decode(nibble));
+    }
+}
+
+/// Decode MS-ADPCM data into interleaved 16-bit samples.
+/// `sample_frames` limits the number of frames, it is given by `fact` chunk.
+pub fn decode(data: &[u8], format: &MsAdpcmFormat, sample_frames: Option<usize>) -> Vec<i16> {
+    let mut samples = Vec::new();
+    if format.block_align == 0 {
+        return samples;
+    }
+    for block in data.chunks(format.block_align) {
+        decode_block(block, format, &mut samples);
+    }
+    if let Some(sample_frames) = sample_frames {
+        samples.truncate(sample_frames * format.channels);
+    }
+
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn decode_monaural_block() {
+        let format = MsAdpcmFormat {
+            channels: 1,
+            block_align: 9,
+            samples_per_block: 6,
+            coefficients: STANDARD_COEFFICIENTS.to_vec(),
+        };
+        // predictor 0, delta 16, sample1 100, sample2 50
+        let block = [0, 16, 0, 100, 0, 50, 0, 0x01, 0xf0];
+
+        assert_eq!(
+            vec![50, 100, 100, 116, 100, 100],
+            decode(&block, &format, None)
+        );
+        assert_eq!(vec![50, 100, 100], decode(&block, &format, Some(3)));
+    }
+
+    #[test]
+    fn decode_hostile_block() {
+        let format = MsAdpcmFormat {
+            channels: 1,
+            block_align: 512,
+            samples_per_block: 1012,
+            coefficients: vec![(i16::MIN as i32, i16::MIN as i32)],
+        };
+        // the largest delta and samples, then nibbles which grow delta most
+        let mut block = vec![0, 0xff, 0x7f, 0x00, 0x80, 0x00, 0x80];
+        block.resize(512, 0x88);
+
+        let samples = decode(&block, &format, None);
+        assert_eq!(1012, samples.len());
+        assert!(samples[2..]
+            .iter()
+            .all(|sample| *sample == i16::MIN || *sample == i16::MAX));
+    }
+}
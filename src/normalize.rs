@@ -1,6 +1,13 @@
 use num_traits::{FromPrimitive, ToPrimitive};
 use std::ops::{Add, Mul, Sub};
 
+/// Whether samples are integers or IEEE floating point numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int,
+    Float,
+}
+
 pub trait MinMax {
     const MIN: Self;
     const MAX: Self;
@@ -15,8 +22,204 @@ impl MinMax for i16 {
     const MIN: i16 = i16::MIN;
     const MAX: i16 = i16::MAX;
 }
+
+impl MinMax for I24 {
+    const MIN: I24 = I24(-(1 << 23));
+    const MAX: I24 = I24((1 << 23) - 1);
+}
+
+impl MinMax for i32 {
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+}
+
+// Floating point samples are already in the range from -1.0 to 1.0
+impl MinMax for f32 {
+    const MIN: f32 = -1.0;
+    const MAX: f32 = 1.0;
+}
+
+impl MinMax for f64 {
+    const MIN: f64 = -1.0;
+    const MAX: f64 = 1.0;
+}
+
+/// Signed 24-bit integer sample which is packed into 3 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct I24(i32);
+
+impl I24 {
+    /// Returns `None` if the value is out of 24-bit range.
+    pub fn new(value: i32) -> Option<I24> {
+        if (I24::MIN.0..=I24::MAX.0).contains(&value) {
+            Some(I24(value))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> i32 {
+        self.0
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 3]) -> I24 {
+        // sign extension by arithmetic shift
+        I24(i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8)
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 3] {
+        let bytes = self.0.to_le_bytes();
+        [bytes[0], bytes[1], bytes[2]]
+    }
+}
+
+impl Add for I24 {
+    type Output = I24;
+    fn add(self, other: I24) -> I24 {
+        I24(self.0 + other.0)
+    }
+}
+
+impl Sub for I24 {
+    type Output = I24;
+    fn sub(self, other: I24) -> I24 {
+        I24(self.0 - other.0)
+    }
+}
+
+impl Mul for I24 {
+    type Output = I24;
+    fn mul(self, other: I24) -> I24 {
+        I24(self.0 * other.0)
+    }
+}
+
+impl ToPrimitive for I24 {
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0 as i64)
+    }
+    fn to_u64(&self) -> Option<u64> {
+        self.0.to_u64()
+    }
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.0 as f64)
+    }
+}
+
+impl FromPrimitive for I24 {
+    fn from_i64(n: i64) -> Option<I24> {
+        I24::new(i32::from_i64(n)?)
+    }
+    fn from_u64(n: u64) -> Option<I24> {
+        I24::new(i32::from_u64(n)?)
+    }
+    fn from_f64(n: f64) -> Option<I24> {
+        I24::new(i32::from_f64(n)?)
+    }
+}
+
 // like trait alias
 // it will be stable in the future https://rust-lang.github.io/rfcs/1733-trait-alias.html
 pub trait GenericNormalize: MinMax + Add + Sub + Mul + FromPrimitive + ToPrimitive {}
 impl GenericNormalize for u8 {}
 impl GenericNormalize for i16 {}
+impl GenericNormalize for I24 {}
+impl GenericNormalize for i32 {}
+impl GenericNormalize for f32 {}
+impl GenericNormalize for f64 {}
+
+/// Symmetric conversion between samples and floating point ones in the range from -1.0 to 1.0.
+/// Integer samples are divided by 2^(bits - 1) around their value of silence,
+/// so that silence is 0.0 and every integer sample survives a round trip.
+pub trait Normalize: GenericNormalize + Copy + Default {
+    /// value of silence
+    const ZERO: f64;
+    /// value of 1.0, which exceeds the maximum of integers by one
+    const SCALE: f64;
+
+    fn to_normalized(self) -> f64 {
+        (self.to_f64().unwrap_or_default() - Self::ZERO) / Self::SCALE
+    }
+
+    /// Sample nearest to `value`, NaN is silence and out of range values are clamped.
+    fn from_normalized(value: f64) -> Self {
+        let checked_value = if value.is_nan() { 0.0 } else { value };
+        Self::from_scaled(checked_value * Self::SCALE + Self::ZERO)
+    }
+
+    /// Sample nearest to `value` which is already scaled into the range of samples.
+    fn from_scaled(value: f64) -> Self {
+        let min = Self::MIN.to_f64().unwrap_or_default();
+        let max = Self::MAX.to_f64().unwrap_or_default();
+        Self::from_f64(value.round().clamp(min, max)).unwrap_or_default()
+    }
+}
+
+impl Normalize for u8 {
+    // unsigned 8-bit samples are offset by 128
+    const ZERO: f64 = 128.0;
+    const SCALE: f64 = 128.0;
+}
+
+impl Normalize for i16 {
+    const ZERO: f64 = 0.0;
+    const SCALE: f64 = 32768.0;
+}
+
+impl Normalize for I24 {
+    const ZERO: f64 = 0.0;
+    const SCALE: f64 = 8388608.0;
+}
+
+impl Normalize for i32 {
+    const ZERO: f64 = 0.0;
+    const SCALE: f64 = 2147483648.0;
+}
+
+// Floating point samples are not rounded
+impl Normalize for f32 {
+    const ZERO: f64 = 0.0;
+    const SCALE: f64 = 1.0;
+
+    fn from_scaled(value: f64) -> Self {
+        value.clamp(-1.0, 1.0) as f32
+    }
+}
+
+impl Normalize for f64 {
+    const ZERO: f64 = 0.0;
+    const SCALE: f64 = 1.0;
+
+    fn from_scaled(value: f64) -> Self {
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn i24_bytes() {
+        for value in [I24::MIN.get(), -1, 0, 1, 0x123456, I24::MAX.get()] {
+            let sample = I24::new(value).unwrap();
+            assert_eq!(sample, I24::from_le_bytes(sample.to_le_bytes()));
+        }
+        assert_eq!(None, I24::new(1 << 23));
+        assert_eq!(Some(I24::MAX), I24::from_f64(8388607.9));
+    }
+
+    #[test]
+    fn symmetric_normalization() {
+        assert_eq!(0.0, 0i16.to_normalized());
+        assert_eq!(0.0, 128u8.to_normalized());
+        assert_eq!(-1.0, i16::MIN.to_normalized());
+        assert_eq!(-1.0, I24::MIN.to_normalized());
+        assert_eq!(0, i16::from_normalized(f64::NAN));
+        assert_eq!(128, u8::from_normalized(f64::NAN));
+        assert_eq!(i16::MAX, i16::from_normalized(1.0));
+        assert_eq!(i16::MIN, i16::from_normalized(-2.0));
+        assert_eq!(1, i16::from_normalized(0.6 / 32768.0));
+        assert_eq!(0.5, f32::from_normalized(0.5));
+        assert_eq!(-1.0, f64::from_normalized(-1.5));
+    }
+}
@@ -0,0 +1,450 @@
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::ops::Range;
+
+/// Management of peaks exceeding full scale before quantization.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum PeakMode {
+    /// samples are clipped at full scale when they are quantized
+    Clip,
+    /// the whole output is scaled so that its peak is the ceiling, it needs the whole output
+    Normalize,
+    /// look-ahead limiter which keeps true peaks under the ceiling
+    Limit,
+    /// samples over the half of the ceiling are saturated smoothly towards it
+    SoftClip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeakConfig {
+    pub mode: PeakMode,
+    /// target of normalization and ceiling of the limiter and soft clipping in dBFS
+    pub ceiling: f64,
+    /// frames which the limiter reduces the gain before a peak
+    pub look_ahead: usize,
+    /// frames which the limiter takes to recover the full gain
+    pub release: usize,
+    pub sample_rate: usize,
+}
+
+impl PeakConfig {
+    /// Ceiling of -1 dBFS, look-ahead of 1.5 ms and release of 50 ms.
+    pub fn new(mode: PeakMode, sample_rate: usize) -> Self {
+        PeakConfig {
+            mode,
+            ceiling: -1.0,
+            look_ahead: (sample_rate * 3 / 2000).max(1),
+            release: (sample_rate / 20).max(1),
+            sample_rate,
+        }
+    }
+
+    pub fn with_ceiling(self, ceiling: f64) -> Self {
+        PeakConfig { ceiling, ..self }
+    }
+
+    /// Linear amplitude of the ceiling.
+    pub fn ceiling_amplitude(&self) -> f64 {
+        10.0_f64.powf(self.ceiling / 20.0)
+    }
+}
+
+/// Samples of the input which exceed full scale, so that they would have clipped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClipReport {
+    pub sample_rate: usize,
+    /// number of clipped samples of all channels
+    pub count: usize,
+    /// consecutive frames which have clipped samples
+    pub regions: Vec<Range<usize>>,
+}
+
+impl ClipReport {
+    /// Beginning of each region in seconds.
+    pub fn timestamps(&self) -> Vec<f64> {
+        self.regions
+            .iter()
+            .map(|region| region.start as f64 / self.sample_rate.max(1) as f64)
+            .collect()
+    }
+
+    fn add(&mut self, frame: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.count += count;
+        match self.regions.last_mut() {
+            Some(region) if region.end == frame => region.end += 1,
+            _ => self.regions.push(frame..frame + 1),
+        }
+    }
+}
+
+/// Half of the taps of the interpolation filter for true peaks.
+const HALF_TAPS: usize = 4;
+/// Oversampling factor of true peaks, same as ITU-R BS.1770.
+const OVERSAMPLING: usize = 4;
+
+/// Look-ahead limiter of true peaks, channels share the same gain.
+#[derive(Debug, Clone)]
+struct Limiter {
+    ceiling: f64,
+    look_ahead: usize,
+    release: usize,
+    /// windowed sinc of each fractional position between samples
+    coefficients: Vec<[f64; 2 * HALF_TAPS]>,
+    /// input samples of each channel from `start`
+    buffer: Vec<Vec<f64>>,
+    start: usize,
+    /// gains needed by frames from `next_output` to `gains_end`
+    gains: VecDeque<f64>,
+    gains_end: usize,
+    next_output: usize,
+    /// gain of the last output frame
+    gain: f64,
+}
+
+impl Limiter {
+    fn new(config: &PeakConfig, channels: usize) -> Self {
+        let coefficients = (1..OVERSAMPLING)
+            .map(|phase| {
+                let mut taps = [0.0; 2 * HALF_TAPS];
+                for (i, tap) in taps.iter_mut().enumerate() {
+                    // distance from the sample `i - HALF_TAPS + 1`
+                    let x =
+                        phase as f64 / OVERSAMPLING as f64 - (i as f64 - HALF_TAPS as f64 + 1.0);
+                    let window = 0.5 * (1.0 + (PI * x / HALF_TAPS as f64).cos());
+                    *tap = (PI * x).sin() / (PI * x) * window;
+                }
+                taps
+            })
+            .collect();
+
+        Limiter {
+            ceiling: config.ceiling_amplitude(),
+            look_ahead: config.look_ahead.max(1),
+            release: config.release.max(1),
+            coefficients,
+            buffer: vec![Vec::new(); channels],
+            start: 0,
+            gains: VecDeque::new(),
+            gains_end: 0,
+            next_output: 0,
+            gain: 1.0,
+        }
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.buffer.first().map_or(0, |channel| channel.len())
+    }
+
+    /// Sample of the channel, which is silence out of the input.
+    fn sample(&self, channel: usize, frame: isize) -> f64 {
+        if frame < self.start as isize {
+            return 0.0;
+        }
+        self.buffer[channel]
+            .get(frame as usize - self.start)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Maximum of the frame and interpolated samples until the next frame.
+    fn true_peak(&self, frame: usize) -> f64 {
+        (0..self.buffer.len())
+            .map(|channel| {
+                let taps: Vec<f64> = (0..2 * HALF_TAPS)
+                    .map(|i| self.sample(channel, (frame + i) as isize - HALF_TAPS as isize + 1))
+                    .collect();
+                self.coefficients
+                    .iter()
+                    .map(|coefficients| {
+                        coefficients
+                            .iter()
+                            .zip(taps.iter())
+                            .map(|(c, x)| c * x)
+                            .sum::<f64>()
+                            .abs()
+                    })
+                    .fold(taps[HALF_TAPS - 1].abs(), f64::max)
+            })
+            .fold(0.0, f64::max)
+    }
+
+    fn push(&mut self, input: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        for (channel, samples) in self.buffer.iter_mut().zip(input) {
+            channel.extend(samples);
+        }
+        self.run(false)
+    }
+
+    fn run(&mut self, finished: bool) -> Vec<Vec<f64>> {
+        let end = self.end();
+        while self.gains_end < end && (finished || self.gains_end + HALF_TAPS < end) {
+            let peak = self.true_peak(self.gains_end);
+            let gain = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+            self.gains.push_back(gain);
+            self.gains_end += 1;
+        }
+
+        let mut output = vec![Vec::new(); self.buffer.len()];
+        while self.next_output < self.gains_end
+            && (finished || self.next_output + self.look_ahead < self.gains_end)
+        {
+            // the gain is reduced linearly towards each peak in the look-ahead
+            let target = self
+                .gains
+                .iter()
+                .take(self.look_ahead + 1)
+                .enumerate()
+                .map(|(distance, gain)| {
+                    gain + (1.0 - gain) * distance as f64 / self.look_ahead as f64
+                })
+                .fold(1.0, f64::min);
+            self.gain = (self.gain + 1.0 / self.release as f64).min(target);
+            for (channel, samples) in output.iter_mut().enumerate() {
+                samples.push(self.buffer[channel][self.next_output - self.start] * self.gain);
+            }
+            self.gains.pop_front();
+            self.next_output += 1;
+        }
+
+        // keep samples which are not output or needed by true peaks of the following frames
+        let keep_from = self
+            .next_output
+            .min(self.gains_end.saturating_sub(HALF_TAPS));
+        for channel in self.buffer.iter_mut() {
+            channel.drain(..keep_from - self.start);
+        }
+        self.start = keep_from;
+
+        output
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Stage {
+    Clip,
+    /// whole output of each channel
+    Normalize(Vec<Vec<f64>>),
+    Limit(Box<Limiter>),
+    SoftClip,
+}
+
+/// Processor of peaks which is fed block by block like `StreamingVocoder`.
+#[derive(Debug, Clone)]
+pub struct PeakProcessor {
+    config: PeakConfig,
+    stage: Stage,
+    channels: usize,
+    report: ClipReport,
+    /// frames fed so far
+    position: usize,
+}
+
+impl PeakProcessor {
+    pub fn new(config: PeakConfig, channels: usize) -> Self {
+        let stage = match config.mode {
+            PeakMode::Clip => Stage::Clip,
+            PeakMode::Normalize => Stage::Normalize(vec![Vec::new(); channels]),
+            PeakMode::Limit => Stage::Limit(Box::new(Limiter::new(&config, channels))),
+            PeakMode::SoftClip => Stage::SoftClip,
+        };
+
+        PeakProcessor {
+            config,
+            stage,
+            channels,
+            report: ClipReport {
+                sample_rate: config.sample_rate,
+                ..Default::default()
+            },
+            position: 0,
+        }
+    }
+
+    pub fn config(&self) -> &PeakConfig {
+        &self.config
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Samples which would have clipped so far.
+    pub fn report(&self) -> &ClipReport {
+        &self.report
+    }
+
+    /// Feed the next block of each channel and return the output samples completed by it.
+    /// Every channel must have the same number of samples.
+    pub fn push_channels<T: AsRef<[f64]>>(&mut self, input: &[T]) -> Vec<Vec<f64>> {
+        let input: Vec<Vec<f64>> = input.iter().map(|c| c.as_ref().to_vec()).collect();
+        let frames = input.first().map_or(0, |channel| channel.len());
+        for frame in 0..frames {
+            let count = input
+                .iter()
+                .filter(|channel| channel[frame].abs() > 1.0)
+                .count();
+            self.report.add(self.position + frame, count);
+        }
+        self.position += frames;
+
+        match &mut self.stage {
+            Stage::Clip => input,
+            Stage::Normalize(buffer) => {
+                for (channel, samples) in buffer.iter_mut().zip(input) {
+                    channel.extend(samples);
+                }
+                vec![Vec::new(); buffer.len()]
+            }
+            Stage::Limit(limiter) => limiter.push(&input),
+            Stage::SoftClip => {
+                let ceiling = self.config.ceiling_amplitude();
+                input
+                    .into_iter()
+                    .map(|channel| channel.iter().map(|x| soft_clip(*x, ceiling)).collect())
+                    .collect()
+            }
+        }
+    }
+
+    /// Return the remaining output samples of each channel and the report of the whole input.
+    pub fn finish_channels(self) -> (Vec<Vec<f64>>, ClipReport) {
+        let output = match self.stage {
+            Stage::Normalize(buffer) => {
+                let peak = buffer
+                    .iter()
+                    .flatten()
+                    .fold(0.0, |peak: f64, x| peak.max(x.abs()));
+                let gain = if peak > 0.0 {
+                    self.config.ceiling_amplitude() / peak
+                } else {
+                    1.0
+                };
+                buffer
+                    .into_iter()
+                    .map(|channel| channel.iter().map(|x| x * gain).collect())
+                    .collect()
+            }
+            Stage::Limit(mut limiter) => limiter.run(true),
+            _ => vec![Vec::new(); self.channels],
+        };
+
+        (output, self.report)
+    }
+}
+
+/// Linear under the half of `ceiling` and saturated towards it over there.
+pub fn soft_clip(value: f64, ceiling: f64) -> f64 {
+    let knee = ceiling / 2.0;
+    if value.abs() <= knee {
+        value
+    } else {
+        let saturated = knee + (ceiling - knee) * ((value.abs() - knee) / (ceiling - knee)).tanh();
+        saturated.copysign(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loud_signal() -> Vec<Vec<f64>> {
+        (0..2)
+            .map(|c| {
+                (0..20000)
+                    .map(|i| {
+                        let envelope = if (5000..6000).contains(&i) { 1.6 } else { 0.4 };
+                        (i as f64 * 0.05 + c as f64).sin() * envelope
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn process(
+        config: PeakConfig,
+        input: &[Vec<f64>],
+        block: usize,
+    ) -> (Vec<Vec<f64>>, ClipReport) {
+        let mut processor = PeakProcessor::new(config, input.len());
+        let mut output = vec![Vec::new(); input.len()];
+        for start in (0..input[0].len()).step_by(block) {
+            let block: Vec<&[f64]> = input
+                .iter()
+                .map(|channel| &channel[start..(start + block).min(channel.len())])
+                .collect();
+            for (channel, samples) in output.iter_mut().zip(processor.push_channels(&block)) {
+                channel.extend(samples);
+            }
+        }
+        let (rest, report) = processor.finish_channels();
+        for (channel, samples) in output.iter_mut().zip(rest) {
+            channel.extend(samples);
+        }
+
+        (output, report)
+    }
+
+    #[test]
+    fn report_clipped_samples() {
+        let input = loud_signal();
+        let (output, report) = process(PeakConfig::new(PeakMode::Clip, 8000), &input, 4096);
+        assert_eq!(input, output);
+        let expected = input.iter().flatten().filter(|x| x.abs() > 1.0).count();
+        assert_eq!(expected, report.count);
+        assert!(report
+            .regions
+            .iter()
+            .all(|region| region.start >= 5000 && region.end <= 6000));
+        assert!(report.timestamps()[0] >= 5000.0 / 8000.0);
+    }
+
+    #[test]
+    fn normalize_peak() {
+        let input = loud_signal();
+        let config = PeakConfig::new(PeakMode::Normalize, 8000).with_ceiling(-6.0);
+        let (output, report) = process(config, &input, 3000);
+        assert!(report.count > 0);
+        let peak = output
+            .iter()
+            .flatten()
+            .fold(0.0, |peak: f64, x| peak.max(x.abs()));
+        assert!((peak - config.ceiling_amplitude()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn limit_true_peaks() {
+        let input = loud_signal();
+        let config = PeakConfig::new(PeakMode::Limit, 8000);
+        let (output, report) = process(config, &input, 1000);
+        let (whole, _) = process(config, &input, input[0].len());
+        assert_eq!(whole, output);
+        assert_eq!(input[0].len(), output[0].len());
+        assert!(report.count > 0);
+
+        let ceiling = config.ceiling_amplitude();
+        for (input, output) in input.iter().zip(output.iter()) {
+            assert!(output.iter().all(|x| x.abs() <= ceiling + 1e-12));
+            // quiet samples far from the loud ones pass through
+            assert_eq!(input[..4000], output[..4000]);
+            assert_eq!(input[input.len() - 1000..], output[output.len() - 1000..]);
+        }
+    }
+
+    #[test]
+    fn soft_clip_is_bounded() {
+        assert_eq!(0.25, soft_clip(0.25, 1.0));
+        assert_eq!(-0.5, soft_clip(-0.5, 1.0));
+        assert!(soft_clip(0.51, 1.0) < 0.51);
+        assert!(soft_clip(100.0, 1.0) <= 1.0);
+        assert!(soft_clip(-100.0, 1.0) >= -1.0);
+        assert!(soft_clip(2.0, 1.0) > soft_clip(1.5, 1.0));
+    }
+}
@@ -3,27 +3,22 @@ use std::f64::consts::PI;
 pub fn principal_argument(phase_in: f64) -> f64 {
     let a = phase_in / (2.0 * PI);
     let k = a.round();
-    let phase_out = phase_in - k * (2.0 * PI);
 
-    phase_out
+    phase_in - k * (2.0 * PI)
 }
 
 pub fn hanning_window(n: usize) -> Vec<f64> {
-    let mut window = vec![0.0; n];
-
     let two_pi = 2.0 * PI;
 
-    for i in 0..n {
-        window[i] = 0.5 - 0.5 * (two_pi * i as f64 / n as f64).cos()
-    }
-
-    window
+    (0..n)
+        .map(|i| 0.5 - 0.5 * (two_pi * i as f64 / n as f64).cos())
+        .collect()
 }
 
 pub fn interpolation(
     fft_size: &usize,
     interpolate_length: &usize,
-    synthesized_buffer: &Vec<f64>,
+    synthesized_buffer: &[f64],
     ratio: &f64,
 ) -> Vec<f64> {
     let factor = 1.0 / ratio;
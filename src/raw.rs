@@ -0,0 +1,259 @@
+use std::io::{Read, Write};
+
+use clap::ValueEnum;
+
+use crate::aiff::swap_bytes;
+use crate::dither::{DitherConfig, Quantizer};
+use crate::error::{Error, Result};
+use crate::normalize::SampleFormat;
+use crate::read::{decode_samples, deinterleave, read_fully};
+use crate::spec::WaveSpec;
+use crate::write::{encode_samples, encode_samples_dithered, interleave};
+
+/// Sample format of headerless samples, whose names follow the ones of sox and ffmpeg.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum RawFormat {
+    /// unsigned 8-bit integers
+    U8,
+    S16le,
+    S16be,
+    S24le,
+    S24be,
+    S32le,
+    S32be,
+    F32le,
+    F32be,
+    F64le,
+    F64be,
+}
+
+impl RawFormat {
+    pub fn sample_format(&self) -> SampleFormat {
+        match self {
+            RawFormat::F32le | RawFormat::F32be | RawFormat::F64le | RawFormat::F64be => {
+                SampleFormat::Float
+            }
+            _ => SampleFormat::Int,
+        }
+    }
+
+    pub fn bits_per_sample(&self) -> usize {
+        match self {
+            RawFormat::U8 => 8,
+            RawFormat::S16le | RawFormat::S16be => 16,
+            RawFormat::S24le | RawFormat::S24be => 24,
+            RawFormat::S32le | RawFormat::S32be | RawFormat::F32le | RawFormat::F32be => 32,
+            RawFormat::F64le | RawFormat::F64be => 64,
+        }
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        matches!(
+            self,
+            RawFormat::S16be
+                | RawFormat::S24be
+                | RawFormat::S32be
+                | RawFormat::F32be
+                | RawFormat::F64be
+        )
+    }
+
+    /// Little-endian format of samples of wave files, which is `None` for other bits.
+    pub fn from_spec(spec: &WaveSpec) -> Option<Self> {
+        match (spec.sample_format, spec.bits_per_sample) {
+            (SampleFormat::Int, 8) => Some(RawFormat::U8),
+            (SampleFormat::Int, 16) => Some(RawFormat::S16le),
+            (SampleFormat::Int, 24) => Some(RawFormat::S24le),
+            (SampleFormat::Int, 32) => Some(RawFormat::S32le),
+            (SampleFormat::Float, 32) => Some(RawFormat::F32le),
+            (SampleFormat::Float, 64) => Some(RawFormat::F64le),
+            _ => None,
+        }
+    }
+
+    /// Format of samples of `channels` channels at `sample_rate`.
+    pub fn spec(&self, channels: usize, sample_rate: usize) -> WaveSpec {
+        WaveSpec {
+            channels,
+            sample_rate,
+            sample_format: self.sample_format(),
+            bits_per_sample: self.bits_per_sample(),
+        }
+    }
+}
+
+/// Reader of headerless interleaved samples from any source like stdin.
+#[derive(Debug)]
+pub struct RawReader<R> {
+    reader: R,
+    format: RawFormat,
+    spec: WaveSpec,
+}
+
+impl<R: Read> RawReader<R> {
+    /// Reader of samples of `channels` channels at `sample_rate`, which are not 0.
+    pub fn new(reader: R, format: RawFormat, channels: usize, sample_rate: usize) -> Result<Self> {
+        if channels == 0 {
+            return Err(Error::InvalidChannels(channels));
+        }
+        if sample_rate == 0 {
+            return Err(Error::UnsupportedSampleRate(sample_rate, "raw samples"));
+        }
+
+        Ok(RawReader {
+            reader,
+            format,
+            spec: format.spec(channels, sample_rate),
+        })
+    }
+
+    pub fn format(&self) -> RawFormat {
+        self.format
+    }
+
+    pub fn spec(&self) -> WaveSpec {
+        self.spec
+    }
+
+    /// Read at most `frames` frames as samples of each channel, they are empty at the end.
+    /// A partial frame at the end of the source is dropped.
+    pub fn read_frames(&mut self, frames: usize) -> Result<Vec<Vec<f64>>> {
+        let block_align = self.spec.block_align().max(1);
+        let mut bytes = vec![0; frames * block_align];
+        let filled = read_fully(&mut self.reader, &mut bytes)?;
+        bytes.truncate(filled / block_align * block_align);
+        if self.format.is_big_endian() {
+            swap_bytes(&mut bytes, self.spec.bits_per_sample / 8);
+        }
+        let samples = decode_samples(&bytes, self.spec.sample_format, self.spec.bits_per_sample)?;
+
+        Ok(deinterleave(&samples, self.spec.channels))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Writer of headerless interleaved samples into any destination like stdout.
+#[derive(Debug)]
+pub struct RawWriter<W: Write> {
+    writer: W,
+    format: RawFormat,
+    channels: usize,
+    /// quantizer with dither, samples are just scaled if it is not given
+    quantizer: Option<Quantizer>,
+}
+
+impl<W: Write> RawWriter<W> {
+    pub fn new(writer: W, format: RawFormat, channels: usize) -> Self {
+        RawWriter {
+            writer,
+            format,
+            channels,
+            quantizer: None,
+        }
+    }
+
+    /// Writer which quantizes samples with dither and noise shaping.
+    pub fn with_dither(mut self, config: DitherConfig) -> Self {
+        self.quantizer = Some(Quantizer::new(config, self.channels));
+        self
+    }
+
+    pub fn format(&self) -> RawFormat {
+        self.format
+    }
+
+    /// Write samples of each channel, shorter channels are padded with silence.
+    pub fn write_frames<T: AsRef<[f64]>>(&mut self, buffer: &[T]) -> Result<()> {
+        if buffer.len() != self.channels {
+            return Err(Error::ChannelMismatch {
+                expected: self.channels,
+                found: buffer.len(),
+            });
+        }
+        let buffer: Vec<Vec<f64>> = buffer.iter().map(|c| c.as_ref().to_vec()).collect();
+        let interleaved = interleave(&buffer);
+        let (sample_format, bits_per_sample) =
+            (self.format.sample_format(), self.format.bits_per_sample());
+        let mut bytes = match self.quantizer.as_mut() {
+            Some(quantizer) => {
+                encode_samples_dithered(&interleaved, sample_format, bits_per_sample, quantizer)?
+            }
+            None => encode_samples(&interleaved, sample_format, bits_per_sample)?,
+        };
+        if self.format.is_big_endian() {
+            swap_bytes(&mut bytes, bits_per_sample / 8);
+        }
+        self.writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+
+    /// Flush the destination.
+    pub fn finalize(mut self) -> Result<()> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Source which returns a few bytes at once like a pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let size = buf.len().min(self.0.len()).min(5);
+            buf[..size].copy_from_slice(&self.0[..size]);
+            self.0 = &self.0[size..];
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn write_read_raw_formats() {
+        let buffer = vec![vec![0.5, -0.25, 0.0], vec![-1.0, 0.75, 0.125]];
+        for format in RawFormat::value_variants() {
+            let mut writer = RawWriter::new(Vec::new(), *format, 2);
+            writer.write_frames(&buffer).unwrap();
+            let mut bytes = writer.into_inner();
+            assert_eq!(3 * 2 * format.bits_per_sample() / 8, bytes.len());
+            // a partial frame at the end
+            bytes.push(0);
+
+            let mut reader = RawReader::new(Trickle(&bytes), *format, 2, 44100).unwrap();
+            let first = reader.read_frames(2).unwrap();
+            let rest = reader.read_frames(10).unwrap();
+            for (expected, (first, rest)) in buffer.iter().zip(first.iter().zip(rest.iter())) {
+                assert_eq!(expected, &[first.as_slice(), rest].concat());
+            }
+            assert!(reader.read_frames(10).unwrap()[0].is_empty());
+        }
+        // big-endian bytes
+        let mut writer = RawWriter::new(Vec::new(), RawFormat::S16be, 1);
+        writer.write_frames(&[[0.5]]).unwrap();
+        assert_eq!(vec![0x40, 0x00], writer.into_inner());
+
+        assert!(matches!(
+            RawReader::new(&[][..], RawFormat::S16le, 0, 44100),
+            Err(Error::InvalidChannels(0))
+        ));
+        assert!(matches!(
+            RawReader::new(&[][..], RawFormat::S16le, 2, 0),
+            Err(Error::UnsupportedSampleRate(0, _))
+        ));
+        assert_eq!(
+            Some(RawFormat::S24le),
+            RawFormat::from_spec(&RawFormat::S24be.spec(2, 44100))
+        );
+    }
+}
@@ -1,8 +1,16 @@
-use core::num::ParseIntError;
-use std::{fs::File, io::Read, str, string::FromUtf8Error};
-use thiserror::Error;
+use std::{
+    fs::File,
+    io::{ErrorKind, Read},
+};
 
-use crate::normalize::GenericNormalize;
+use crate::error::{Error, Result};
+use crate::g711::Law;
+use crate::ima_adpcm::{self, ImaAdpcmFormat};
+use crate::metadata::MetadataChunk;
+use crate::ms_adpcm::{self, MsAdpcmFormat};
+use crate::normalize::{Normalize, SampleFormat, I24};
+use crate::riff::{is_rf64, rf64_ds64, riff_chunks, Chunk, RF64_SIZE};
+use crate::spec::{ChannelLayout, Codec, WaveSpec};
 
 #[derive(Debug, Clone)]
 pub struct Wave {
@@ -11,126 +19,600 @@ pub struct Wave {
     pub riff_type: String,
     pub format_str: String,
     pub format_data_size: usize,
-    pub compression_code: String,
-    pub channels: String,
+    pub compression_code: Codec,
+    pub channels: ChannelLayout,
+    pub number_of_channels: usize,
     pub sample_rate: usize,
     pub bytes_per_second: usize,
     pub block_align: usize,
     pub bits_per_sample: usize,
+    /// bits per sample of linear PCM which decoded samples fit in
+    pub pcm_bits_per_sample: usize,
+    pub sample_format: SampleFormat,
+    /// G.711 law which samples are companded with
+    pub companding_law: Option<Law>,
+    pub extensible: Option<FormatExtensible>,
     pub data_str: String,
     pub chunk_data_size: usize,
     pub file_size: usize,
-    pub normalized_sample_data: Vec<f64>,
+    /// samples of each channel
+    pub normalized_sample_data: Vec<Vec<f64>>,
+    /// chunks other than `fmt ` and `data`
+    pub skipped_chunks: Vec<Chunk>,
+    /// data of skipped chunks which are carried to the output
+    pub metadata: Vec<MetadataChunk>,
 }
-#[derive(Error, Debug)]
-pub enum WaveParseError {
-    #[error("failed to open file")]
-    IoError(#[from] std::io::Error),
-    #[error("failed to convert bytes to utf-8 string")]
-    FromUtf8Error(#[from] FromUtf8Error),
-    #[error("failed to convert string to hexadecimal integer")]
-    ParseIntError(#[from] ParseIntError),
+
+impl Wave {
+    /// Format of linear PCM or IEEE float which decoded samples fit in.
+    pub fn spec(&self) -> WaveSpec {
+        WaveSpec {
+            channels: self.number_of_channels,
+            sample_rate: self.sample_rate,
+            sample_format: self.sample_format,
+            bits_per_sample: self.pcm_bits_per_sample,
+        }
+    }
 }
 
-pub type WaveResult<T> = Result<T, WaveParseError>;
+/// GUID of sub format following its format code, KSDATAFORMAT_SUBTYPE_PCM and so on.
+const SUB_FORMAT_GUID: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
 
-fn byte_vec_to_num(bytes: &mut Vec<u8>) -> Result<usize, ParseIntError> {
-    // reverse byte vector from little-endian
-    bytes.reverse();
-    let hexadecimal = bytes
-        .iter()
-        .map(|n| format!("{:02X}", n))
-        .collect::<String>();
-    let number = usize::from_str_radix(&hexadecimal, 16)?;
+/// Extension of `fmt ` chunk for WAVE_FORMAT_EXTENSIBLE.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatExtensible {
+    pub valid_bits_per_sample: usize,
+    /// speaker position of each channel
+    pub channel_mask: usize,
+    pub sub_format: [u8; 16],
+}
+
+impl FormatExtensible {
+    pub fn new(codec: Codec, valid_bits_per_sample: usize, channel_mask: usize) -> Self {
+        let mut sub_format = [0; 16];
+        sub_format[0..2].copy_from_slice(&codec.code().to_le_bytes());
+        sub_format[2..].copy_from_slice(&SUB_FORMAT_GUID);
+
+        FormatExtensible {
+            valid_bits_per_sample,
+            channel_mask,
+            sub_format,
+        }
+    }
+
+    /// Compression code of the samples, if the sub format is one of the standard ones.
+    pub fn sub_format_codec(&self) -> Option<Codec> {
+        if self.sub_format[2..] == SUB_FORMAT_GUID {
+            Some(Codec::from_code(u16::from_le_bytes([
+                self.sub_format[0],
+                self.sub_format[1],
+            ])))
+        } else {
+            None
+        }
+    }
+
+    /// Bytes following the size of extension.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(22);
+        bytes.extend_from_slice(&(self.valid_bits_per_sample as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.channel_mask as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.sub_format);
+
+        bytes
+    }
+}
+
+/// Little-endian unsigned integer of 2 bytes at `at`.
+pub(crate) fn u16_at(bytes: &[u8], at: usize) -> usize {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize
+}
 
-    Ok(number)
+/// Little-endian unsigned integer of 4 bytes at `at`.
+pub(crate) fn u32_at(bytes: &[u8], at: usize) -> usize {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize
 }
 
-fn normalize<T: GenericNormalize>(value: f64) -> f64 {
-    let max = T::MAX.to_f64().unwrap();
-    let min = T::MIN.to_f64().unwrap();
-    let normalized = ((value - min) / (max - min)) * (1.0 - (-1.0)) - 1.0;
+pub(crate) fn check_magic(buf: &[u8], offset: usize, expected: &'static str) -> Result<()> {
+    let found = &buf[offset..offset + 4];
+    if found == expected.as_bytes() {
+        Ok(())
+    } else {
+        Err(Error::BadMagic {
+            offset,
+            expected,
+            found: String::from_utf8_lossy(found).to_string(),
+        })
+    }
+}
 
-    normalized
+fn decode<T: Normalize, const N: usize>(
+    raw_chunk_data: &[u8],
+    from_le_bytes: fn([u8; N]) -> T,
+) -> Vec<f64> {
+    raw_chunk_data
+        .chunks_exact(N)
+        .map(|a| from_le_bytes(a.try_into().unwrap()).to_normalized())
+        .collect()
 }
 
-pub fn wav_read(filename: &str) -> WaveResult<Wave> {
+/// Normalize little-endian samples into the range from -1.0 to 1.0.
+pub fn decode_samples(
+    raw_chunk_data: &[u8],
+    sample_format: SampleFormat,
+    bits_per_sample: usize,
+) -> Result<Vec<f64>> {
+    let samples = match (sample_format, bits_per_sample) {
+        (SampleFormat::Int, 8) => decode(raw_chunk_data, u8::from_le_bytes),
+        (SampleFormat::Int, 16) => decode(raw_chunk_data, i16::from_le_bytes),
+        (SampleFormat::Int, 24) => decode(raw_chunk_data, I24::from_le_bytes),
+        (SampleFormat::Int, 32) => decode(raw_chunk_data, i32::from_le_bytes),
+        (SampleFormat::Float, 32) => decode(raw_chunk_data, f32::from_le_bytes),
+        (SampleFormat::Float, 64) => decode(raw_chunk_data, f64::from_le_bytes),
+        _ => {
+            return Err(Error::UnsupportedBitsPerSample(
+                bits_per_sample,
+                sample_format,
+            ))
+        }
+    };
+
+    Ok(samples)
+}
+
+/// Split interleaved samples into buffers of each channel, an incomplete last frame is dropped.
+pub fn deinterleave(samples: &[f64], channels: usize) -> Vec<Vec<f64>> {
+    let channels = channels.max(1);
+    let mut buffers = vec![Vec::with_capacity(samples.len() / channels); channels];
+    for frame in samples.chunks_exact(channels) {
+        for (buffer, sample) in buffers.iter_mut().zip(frame.iter()) {
+            buffer.push(*sample);
+        }
+    }
+
+    buffers
+}
+
+/// Read until `buf` is filled or the source ends, and return the number of bytes read.
+/// Pipes may return fewer bytes than requested before their end.
+pub(crate) fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(size) => filled += size,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(filled)
+}
+
+pub fn wav_read(filename: &str) -> Result<Wave> {
     // open local file
     let mut file = File::open(filename)?;
     let mut buf = Vec::new();
     let _ = file.read_to_end(&mut buf)?;
-    // restore data from binary
-    let file_type = String::from_utf8(buf[0..=3].to_vec())?;
-    let riff_chunk_size = byte_vec_to_num(&mut buf[4..=7].to_vec())?;
-    let riff_type = String::from_utf8(buf[8..=11].to_vec())?;
-    let format_str = String::from_utf8(buf[12..=15].to_vec())?;
-    let format_data_size = byte_vec_to_num(&mut buf[16..=19].to_vec())?;
-    let compression_code = match byte_vec_to_num(&mut buf[20..=21].to_vec())? {
-        1 => "Linear PCM".to_string(),
-        2 => "MS-ADPCM".to_string(),
-        5 => "IBM CSVD".to_string(),
-        _ => "Unkown wave format".to_string(),
-    };
-    let channels_num = byte_vec_to_num(&mut buf[22..=23].to_vec())?;
-    let channels = if channels_num == 1 {
-        "Monaural".to_string()
-    } else {
-        "Stereo".to_string()
-    };
-    let sample_rate = byte_vec_to_num(&mut buf[24..=27].to_vec())?;
-    let bytes_per_second = byte_vec_to_num(&mut buf[28..=31].to_vec())?;
-    let block_align = byte_vec_to_num(&mut buf[32..=33].to_vec())?;
-    let bits_per_sample = byte_vec_to_num(&mut buf[34..=35].to_vec())?;
-    let data_str = String::from_utf8(buf[36..=39].to_vec())?;
-    let chunk_data_size = byte_vec_to_num(&mut buf[40..=43].to_vec())?;
-    let file_size = riff_chunk_size + chunk_data_size + 8;
-    let raw_chunk_data = buf[44..=buf.len() - 1].to_vec();
-
-    let data = if bits_per_sample == 8 {
-        Wave {
-            file_type,
-            riff_chunk_size,
-            riff_type,
-            format_str,
-            format_data_size,
-            compression_code,
-            channels,
-            sample_rate,
-            bytes_per_second,
-            block_align,
-            bits_per_sample,
-            data_str,
-            chunk_data_size,
-            file_size,
-            normalized_sample_data: raw_chunk_data
-                .into_iter()
-                .map(|a| normalize::<u8>(a as f64))
-                .collect(),
+
+    parse_wave(&buf)
+}
+
+/// How samples of `data` chunk are decoded.
+#[derive(Debug, Clone)]
+enum SampleDecoder {
+    Linear,
+    G711(Law),
+    MsAdpcm(MsAdpcmFormat),
+    ImaAdpcm(ImaAdpcmFormat),
+}
+
+/// Fields of `fmt ` chunk.
+#[derive(Debug, Clone)]
+pub struct WaveFormat {
+    pub compression_code: Codec,
+    pub number_of_channels: usize,
+    pub sample_rate: usize,
+    pub bytes_per_second: usize,
+    pub block_align: usize,
+    pub bits_per_sample: usize,
+    /// bits per sample of linear PCM which decoded samples fit in
+    pub pcm_bits_per_sample: usize,
+    pub sample_format: SampleFormat,
+    /// G.711 law which samples are companded with
+    pub companding_law: Option<Law>,
+    pub extensible: Option<FormatExtensible>,
+    decoder: SampleDecoder,
+}
+
+impl WaveFormat {
+    /// Parse data of `fmt ` chunk whose header is at `offset`.
+    pub fn parse(fmt: &[u8], offset: usize) -> Result<Self> {
+        let incomplete_format = |format: &str| Error::IncompleteFormatChunk {
+            offset,
+            format: format.to_string(),
+        };
+        if fmt.len() < 16 {
+            return Err(incomplete_format("WAVEFORMAT"));
         }
-    } else {
-        let restored_normalized_sample_data: Vec<f64> = raw_chunk_data
-            .chunks_exact(2)
-            .into_iter()
-            .map(|a| normalize::<i16>(i16::from_le_bytes([a[0], a[1]]) as f64))
-            .collect();
-        Wave {
-            file_type,
-            riff_chunk_size,
-            riff_type,
-            format_str,
-            format_data_size,
+        let compression_code = Codec::from_code(u16_at(fmt, 0) as u16);
+        let number_of_channels = u16_at(fmt, 2);
+        let sample_rate = u32_at(fmt, 4);
+        let bytes_per_second = u32_at(fmt, 8);
+        let block_align = u16_at(fmt, 12);
+        let bits_per_sample = u16_at(fmt, 14);
+        if number_of_channels == 0 {
+            return Err(Error::InvalidChannels(number_of_channels));
+        }
+
+        // WAVE_FORMAT_EXTENSIBLE
+        let extensible = if compression_code == Codec::Extensible {
+            if fmt.len() < 40 {
+                return Err(incomplete_format("WAVE_FORMAT_EXTENSIBLE"));
+            }
+            let mut sub_format = [0; 16];
+            sub_format.copy_from_slice(&fmt[24..40]);
+            Some(FormatExtensible {
+                valid_bits_per_sample: u16_at(fmt, 18),
+                channel_mask: u32_at(fmt, 20),
+                sub_format,
+            })
+        } else {
+            None
+        };
+        // codec of the samples
+        let (sample_codec, sample_codec_offset) = match &extensible {
+            Some(extensible) => (
+                extensible.sub_format_codec().unwrap_or(compression_code),
+                offset + 8 + 24,
+            ),
+            None => (compression_code, offset + 8),
+        };
+
+        let sample_format = if sample_codec == Codec::IeeeFloat {
+            SampleFormat::Float
+        } else {
+            SampleFormat::Int
+        };
+        let companding_law = Law::from_codec(sample_codec);
+        let check_block_align = |consistent: bool, expected: String| {
+            if consistent {
+                Ok(())
+            } else {
+                Err(Error::InconsistentBlockAlign {
+                    offset: offset + 8 + 12,
+                    block_align,
+                    expected,
+                })
+            }
+        };
+        // bytes of a frame of linear PCM, IEEE float and G.711
+        let frame_size = number_of_channels * bits_per_sample.div_ceil(8);
+        let (decoder, pcm_bits_per_sample) = match (sample_codec, companding_law) {
+            (Codec::MsAdpcm, _) => {
+                // block header
+                check_block_align(
+                    block_align >= 7 * number_of_channels,
+                    format!("at least {}", 7 * number_of_channels),
+                )?;
+                let format = MsAdpcmFormat::from_extra_fields(
+                    number_of_channels,
+                    block_align,
+                    fmt.get(18..).unwrap_or_default(),
+                )
+                .ok_or_else(|| incomplete_format(&sample_codec.to_string()))?;
+                (SampleDecoder::MsAdpcm(format), 16)
+            }
+            (Codec::ImaAdpcm, _) => {
+                // block header and 4 bytes of each channel
+                let group_size = 4 * number_of_channels.max(1);
+                check_block_align(
+                    block_align >= group_size && block_align.is_multiple_of(group_size),
+                    format!("a multiple of {}", group_size),
+                )?;
+                let format = ImaAdpcmFormat::from_extra_fields(
+                    number_of_channels,
+                    block_align,
+                    fmt.get(18..).unwrap_or_default(),
+                );
+                (SampleDecoder::ImaAdpcm(format), 16)
+            }
+            (_, Some(law)) => {
+                check_block_align(
+                    block_align == number_of_channels,
+                    number_of_channels.to_string(),
+                )?;
+                (SampleDecoder::G711(law), 16)
+            }
+            (Codec::Pcm | Codec::IeeeFloat, _) => {
+                check_block_align(block_align == frame_size, frame_size.to_string())?;
+                // fail before reading samples
+                decode_samples(&[], sample_format, bits_per_sample)?;
+                (SampleDecoder::Linear, bits_per_sample)
+            }
+            (codec, _) => {
+                return Err(Error::UnsupportedCodec {
+                    offset: sample_codec_offset,
+                    code: codec.code() as usize,
+                })
+            }
+        };
+
+        Ok(WaveFormat {
             compression_code,
-            channels,
+            number_of_channels,
             sample_rate,
             bytes_per_second,
             block_align,
             bits_per_sample,
-            data_str,
-            chunk_data_size,
-            file_size,
-            normalized_sample_data: restored_normalized_sample_data,
+            pcm_bits_per_sample,
+            sample_format,
+            companding_law,
+            extensible,
+            decoder,
+        })
+    }
+
+    /// Format of linear PCM or IEEE float which decoded samples fit in.
+    pub fn spec(&self) -> WaveSpec {
+        WaveSpec {
+            channels: self.number_of_channels,
+            sample_rate: self.sample_rate,
+            sample_format: self.sample_format,
+            bits_per_sample: self.pcm_bits_per_sample,
+        }
+    }
+
+    /// Number of frames which a block of `block_align` bytes has.
+    pub fn frames_per_block(&self) -> usize {
+        match &self.decoder {
+            SampleDecoder::MsAdpcm(format) => format.samples_per_block,
+            SampleDecoder::ImaAdpcm(format) => format.samples_per_block,
+            _ => 1,
+        }
+    }
+
+    /// Decode whole blocks into interleaved samples in the range from -1.0 to 1.0.
+    /// `sample_frames` limits the number of frames of compressed data.
+    pub fn decode(&self, raw_chunk_data: &[u8], sample_frames: Option<usize>) -> Result<Vec<f64>> {
+        let from_i16 = |samples: Vec<i16>| samples.into_iter().map(|a| a.to_normalized()).collect();
+        let samples = match &self.decoder {
+            SampleDecoder::Linear => {
+                decode_samples(raw_chunk_data, self.sample_format, self.bits_per_sample)?
+            }
+            SampleDecoder::G711(law) => raw_chunk_data
+                .iter()
+                .map(|a| law.decode(*a).to_normalized())
+                .collect(),
+            SampleDecoder::MsAdpcm(format) => {
+                from_i16(ms_adpcm::decode(raw_chunk_data, format, sample_frames))
+            }
+            SampleDecoder::ImaAdpcm(format) => {
+                from_i16(ima_adpcm::decode(raw_chunk_data, format, sample_frames))
+            }
+        };
+
+        Ok(samples)
+    }
+}
+
+/// Restore wave from the binary of whole file.
+pub fn parse_wave(buf: &[u8]) -> Result<Wave> {
+    // RIFF header
+    if buf.len() < 12 {
+        return Err(Error::TruncatedHeader {
+            name: "RIFF header",
+            offset: 0,
+            size: 12,
+            file_size: buf.len(),
+        });
+    }
+    let ds64 = rf64_ds64(buf);
+    if is_rf64(&buf[0..4]) && ds64.is_none() {
+        return Err(Error::MissingChunk("ds64"));
+    } else if ds64.is_none() {
+        check_magic(buf, 0, "RIFF")?;
+    }
+    check_magic(buf, 8, "WAVE")?;
+    let file_type = String::from_utf8_lossy(&buf[0..4]).to_string();
+    let riff_chunk_size = match &ds64 {
+        Some(ds64) => ds64.riff_size as usize,
+        None => u32_at(buf, 4),
+    };
+    let riff_type = String::from_utf8_lossy(&buf[8..12]).to_string();
+
+    // `fmt ` and `data` chunk can be anywhere, other chunks are skipped.
+    let mut format_chunk = None;
+    let mut data_chunk = None;
+    let mut skipped_chunks = Vec::new();
+    for chunk in riff_chunks(buf) {
+        match &chunk.id {
+            b"fmt " if format_chunk.is_none() => format_chunk = Some(chunk),
+            b"data" if data_chunk.is_none() => data_chunk = Some(chunk),
+            _ => skipped_chunks.push(chunk),
         }
+    }
+    let format_chunk = format_chunk.ok_or(Error::MissingChunk("fmt "))?;
+    let data_chunk = data_chunk.ok_or(Error::MissingChunk("data"))?;
+    if data_chunk.offset.saturating_add(data_chunk.size) > buf.len() {
+        return Err(Error::DataExceedsFile {
+            offset: data_chunk.offset - 8,
+            size: data_chunk.size,
+            file_size: buf.len(),
+        });
+    }
+    let format = WaveFormat::parse(format_chunk.data(buf), format_chunk.offset - 8)?;
+
+    // Number of sample frames of compressed data
+    let fact_sample_frames = skipped_chunks
+        .iter()
+        .find(|chunk| &chunk.id == b"fact")
+        .and_then(|chunk| chunk.data(buf).get(0..4))
+        .map(|a| match &ds64 {
+            Some(ds64) if u32_at(a, 0) == RF64_SIZE as usize => ds64.sample_count as usize,
+            _ => u32_at(a, 0),
+        });
+    let interleaved = format.decode(data_chunk.data(buf), fact_sample_frames)?;
+
+    let data = Wave {
+        file_type,
+        riff_chunk_size,
+        riff_type,
+        format_str: format_chunk.name(),
+        format_data_size: format_chunk.size,
+        compression_code: format.compression_code,
+        channels: ChannelLayout::from_count(format.number_of_channels),
+        number_of_channels: format.number_of_channels,
+        sample_rate: format.sample_rate,
+        bytes_per_second: format.bytes_per_second,
+        block_align: format.block_align,
+        bits_per_sample: format.bits_per_sample,
+        pcm_bits_per_sample: format.pcm_bits_per_sample,
+        sample_format: format.sample_format,
+        companding_law: format.companding_law,
+        normalized_sample_data: deinterleave(&interleaved, format.number_of_channels),
+        extensible: format.extensible,
+        data_str: data_chunk.name(),
+        chunk_data_size: data_chunk.size,
+        file_size: riff_chunk_size.saturating_add(8),
+        metadata: skipped_chunks
+            .iter()
+            .filter(|chunk| MetadataChunk::is_metadata(&chunk.id))
+            .map(|chunk| MetadataChunk::new(chunk.id, chunk.data(buf).to_vec()))
+            .collect(),
+        skipped_chunks,
     };
 
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_wave_with_other_chunks() {
+        let mut buf = b"RIFF\x00\x00\x00\x00WAVE".to_vec();
+        buf.extend(b"LIST\x05\x00\x00\x00INFOa\x00");
+        // fmt chunk with extra bytes
+        buf.extend(b"fmt \x12\x00\x00\x00");
+        buf.extend([
+            1, 0, 1, 0, 0x40, 0x1f, 0, 0, 0x80, 0x3e, 0, 0, 2, 0, 16, 0, 0, 0,
+        ]);
+        buf.extend(b"data\x04\x00\x00\x00\x00\x00\xff\x7f");
+        buf.extend(b"JUNK\x02\x00\x00\x00\x00\x00");
+        let riff_chunk_size = (buf.len() - 8) as u32;
+        buf[4..8].copy_from_slice(&riff_chunk_size.to_le_bytes());
+
+        let wave = parse_wave(&buf).unwrap();
+        assert_eq!(8000, wave.sample_rate);
+        assert_eq!(16, wave.bits_per_sample);
+        assert_eq!(4, wave.chunk_data_size);
+        assert_eq!(buf.len(), wave.file_size);
+        assert_eq!(2, wave.normalized_sample_data[0].len());
+        assert_eq!(
+            vec!["LIST", "JUNK"],
+            wave.skipped_chunks
+                .iter()
+                .map(|c| c.name())
+                .collect::<Vec<_>>()
+        );
+        // padding is not metadata
+        assert_eq!(
+            vec![MetadataChunk::new(*b"LIST", b"INFOa".to_vec())],
+            wave.metadata
+        );
+    }
+
+    #[test]
+    fn reject_wave_without_channels() {
+        let mut buf = b"RIFF\x24\x00\x00\x00WAVE".to_vec();
+        buf.extend(b"fmt \x10\x00\x00\x00");
+        buf.extend([1, 0, 0, 0, 0x40, 0x1f, 0, 0, 0, 0, 0, 0, 0, 0, 16, 0]);
+        buf.extend(b"data\x00\x00\x00\x00");
+
+        assert!(matches!(parse_wave(&buf), Err(Error::InvalidChannels(0))));
+    }
+
+    #[test]
+    fn parse_ms_adpcm_wave() {
+        let mut buf = b"RIFF\x00\x00\x00\x00WAVE".to_vec();
+        buf.extend(b"fmt \x32\x00\x00\x00");
+        buf.extend([2, 0, 1, 0, 0x40, 0x1f, 0, 0, 0, 0, 0, 0, 9, 0, 4, 0, 32, 0]);
+        // samples per block and coefficients
+        buf.extend([6, 0, 7, 0]);
+        for (a, b) in crate::ms_adpcm::STANDARD_COEFFICIENTS {
+            buf.extend((a as i16).to_le_bytes());
+            buf.extend((b as i16).to_le_bytes());
+        }
+        buf.extend(b"fact\x04\x00\x00\x00\x05\x00\x00\x00");
+        buf.extend(b"data\x09\x00\x00\x00");
+        buf.extend([0, 16, 0, 100, 0, 50, 0, 0x01, 0xf0, 0]);
+
+        let wave = parse_wave(&buf).unwrap();
+        assert_eq!(16, wave.pcm_bits_per_sample);
+        assert_eq!(5, wave.normalized_sample_data[0].len());
+        assert!(wave.normalized_sample_data[0][3] > wave.normalized_sample_data[0][2]);
+    }
+
+    #[test]
+    fn reject_invalid_waves() {
+        let mut valid = b"RIFF\x00\x00\x00\x00WAVE".to_vec();
+        valid.extend(b"fmt \x10\x00\x00\x00");
+        valid.extend([1, 0, 1, 0, 0x40, 0x1f, 0, 0, 0x80, 0x3e, 0, 0, 2, 0, 16, 0]);
+        valid.extend(b"data\x04\x00\x00\x00\x00\x00\xff\x7f");
+        assert!(parse_wave(&valid).is_ok());
+
+        assert!(matches!(
+            parse_wave(&valid[0..10]),
+            Err(Error::TruncatedHeader { file_size: 10, .. })
+        ));
+
+        let mut buf = valid.clone();
+        buf[8..12].copy_from_slice(b"AVI ");
+        assert!(matches!(
+            parse_wave(&buf),
+            Err(Error::BadMagic {
+                offset: 8,
+                expected: "WAVE",
+                ..
+            })
+        ));
+
+        let mut buf = valid.clone();
+        buf[20] = 0x55;
+        assert!(matches!(
+            parse_wave(&buf),
+            Err(Error::UnsupportedCodec {
+                offset: 20,
+                code: 0x55
+            })
+        ));
+
+        let mut buf = valid.clone();
+        buf[32] = 4;
+        assert!(matches!(
+            parse_wave(&buf),
+            Err(Error::InconsistentBlockAlign {
+                offset: 32,
+                block_align: 4,
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            parse_wave(&valid[0..valid.len() - 1]),
+            Err(Error::DataExceedsFile {
+                offset: 36,
+                size: 4,
+                file_size: 47
+            })
+        ));
+
+        assert!(matches!(
+            parse_wave(&valid[0..36]),
+            Err(Error::MissingChunk("data"))
+        ));
+    }
+}
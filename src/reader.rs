@@ -0,0 +1,488 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+use crate::error::{Error, Result};
+use crate::metadata::MetadataChunk;
+use crate::read::{check_magic, deinterleave, read_fully, u32_at, WaveFormat};
+use crate::riff::{is_rf64, Chunk, Ds64, RF64_SIZE};
+use crate::spec::WaveSpec;
+
+/// Frames read from the source at least at once.
+const MIN_READ_FRAMES: usize = 4096;
+
+/// Data of a chunk, which grows as it arrives rather than being allocated by the size field.
+fn read_data<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(size as u64).read_to_end(&mut data)?;
+    if data.len() < size {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(data)
+}
+
+/// Reader which parses the header first and then decodes samples as they are needed.
+#[derive(Debug)]
+pub struct WaveReader<R> {
+    reader: R,
+    format: WaveFormat,
+    /// `data` chunk
+    data_chunk: Chunk,
+    /// chunks other than `fmt ` and `data`
+    skipped_chunks: Vec<Chunk>,
+    /// data of skipped chunks which are carried to the output
+    metadata: Vec<MetadataChunk>,
+    /// number of sample frames of compressed data given by `fact` chunk
+    fact_sample_frames: Option<usize>,
+    /// bytes of `data` chunk which are not read yet
+    remaining: usize,
+    /// frames which can be decoded yet
+    frames_left: Option<usize>,
+    /// interleaved samples which are decoded but not returned yet
+    pending: VecDeque<f64>,
+}
+
+impl<R: Read + Seek> WaveReader<R> {
+    /// Parse the header, the source is left at the beginning of samples.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let file_size = reader.seek(SeekFrom::End(0))? as usize;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut wave = Self::parse(reader, Some(file_size), |reader, size| {
+            reader.seek(SeekFrom::Current(size as i64)).map(|_| ())
+        })?;
+        let offset = wave.data_chunk.offset as u64;
+        wave.reader.seek(SeekFrom::Start(offset))?;
+
+        Ok(wave)
+    }
+}
+
+impl<R: Read> WaveReader<R> {
+    /// Parse the header of a stream like stdin forward only, the source is left at the beginning of samples.
+    /// Chunks after `data` chunk are not read, and `data` chunk of 0 or 0xFFFFFFFF bytes lasts until the end.
+    pub fn from_stream(reader: R) -> Result<Self> {
+        Self::parse(reader, None, |reader, size| {
+            io::copy(&mut reader.take(size as u64), &mut io::sink()).map(|_| ())
+        })
+    }
+
+    /// Walk chunks with their headers, only `fmt `, `fact`, `ds64` and metadata chunks are read.
+    /// `skip` moves the source forward, and the walk stops at `data` chunk if `file_size` is unknown.
+    fn parse(
+        mut reader: R,
+        file_size: Option<usize>,
+        skip: fn(&mut R, usize) -> io::Result<()>,
+    ) -> Result<Self> {
+        // RIFF header
+        let mut header = [0; 12];
+        let read = read_fully(&mut reader, &mut header)?;
+        if read < header.len() {
+            return Err(Error::TruncatedHeader {
+                name: "RIFF header",
+                offset: 0,
+                size: header.len(),
+                file_size: read,
+            });
+        }
+        let rf64 = is_rf64(&header[0..4]);
+        if !rf64 {
+            check_magic(&header, 0, "RIFF")?;
+        }
+        check_magic(&header, 8, "WAVE")?;
+
+        let mut format = None;
+        let mut data_chunk = None;
+        let mut fact_sample_frames = None;
+        let mut skipped_chunks = Vec::new();
+        let mut metadata = Vec::new();
+        let mut ds64: Option<Ds64> = None;
+        // bytes of `data` chunk, which is unknown for a stream of a placeholder size
+        let mut remaining = None;
+        let mut position = header.len();
+        while file_size.is_none_or(|file_size| position + 8 <= file_size) {
+            let mut chunk_header = [0; 8];
+            if read_fully(&mut reader, &mut chunk_header)? < chunk_header.len() {
+                break;
+            }
+            let chunk = Chunk {
+                id: [
+                    chunk_header[0],
+                    chunk_header[1],
+                    chunk_header[2],
+                    chunk_header[3],
+                ],
+                offset: position.saturating_add(8),
+                size: u32_at(&chunk_header, 4),
+            };
+            // 64-bit sizes of RF64
+            let chunk = match &ds64 {
+                Some(ds64) => Chunk {
+                    size: ds64.chunk_size(&chunk.id, chunk.size),
+                    ..chunk
+                },
+                None => chunk,
+            };
+            // samples of a stream follow, which cannot be passed over
+            if file_size.is_none() && &chunk.id == b"data" {
+                if chunk.size == 0 || chunk.size == RF64_SIZE as usize {
+                    data_chunk = Some(Chunk { size: 0, ..chunk });
+                    remaining = Some(usize::MAX);
+                } else {
+                    data_chunk = Some(chunk);
+                }
+                break;
+            }
+            // chunk data truncated by the end of file
+            let available = file_size.map_or(chunk.size, |file_size| {
+                chunk.size.min(file_size - chunk.offset)
+            });
+            // bytes of the chunk which are read
+            let mut consumed = 0;
+            match &chunk.id {
+                b"ds64" if rf64 && ds64.is_none() => {
+                    ds64 = Ds64::parse(&read_data(&mut reader, available)?);
+                    consumed = available;
+                    skipped_chunks.push(chunk.clone());
+                }
+                b"fmt " if format.is_none() => {
+                    let fmt = read_data(&mut reader, available)?;
+                    consumed = available;
+                    format = Some(WaveFormat::parse(&fmt, position)?);
+                }
+                b"data" if data_chunk.is_none() => data_chunk = Some(chunk.clone()),
+                _ => {
+                    if &chunk.id == b"fact" && available >= 4 {
+                        let fact = read_data(&mut reader, 4)?;
+                        consumed = 4;
+                        fact_sample_frames = Some(u32_at(&fact, 0));
+                        if let Some(ds64) =
+                            ds64.as_ref().filter(|_| fact == RF64_SIZE.to_le_bytes())
+                        {
+                            fact_sample_frames = Some(ds64.sample_count as usize);
+                        }
+                    } else if MetadataChunk::is_metadata(&chunk.id) {
+                        let data = read_data(&mut reader, available)?;
+                        consumed = available;
+                        metadata.push(MetadataChunk::new(chunk.id, data));
+                    }
+                    skipped_chunks.push(chunk.clone());
+                }
+            }
+            // sizes of `ds64` chunk may claim more bytes than the file or the address space has
+            position = chunk.offset.saturating_add(chunk.padded_size());
+            if file_size.is_some_and(|file_size| position > file_size) {
+                break;
+            }
+            skip(&mut reader, chunk.padded_size() - consumed)?;
+        }
+        if rf64 && ds64.is_none() {
+            return Err(Error::MissingChunk("ds64"));
+        }
+        let format = format.ok_or(Error::MissingChunk("fmt "))?;
+        let data_chunk = data_chunk.ok_or(Error::MissingChunk("data"))?;
+        if let Some(file_size) = file_size
+            .filter(|file_size| data_chunk.offset.saturating_add(data_chunk.size) > *file_size)
+        {
+            return Err(Error::DataExceedsFile {
+                offset: data_chunk.offset - 8,
+                size: data_chunk.size,
+                file_size,
+            });
+        }
+        // linear PCM is not limited by `fact` chunk
+        let fact_sample_frames = fact_sample_frames.filter(|_| format.frames_per_block() > 1);
+
+        Ok(WaveReader {
+            reader,
+            format,
+            remaining: remaining.unwrap_or(data_chunk.size),
+            data_chunk,
+            skipped_chunks,
+            metadata,
+            fact_sample_frames,
+            frames_left: fact_sample_frames,
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn format(&self) -> &WaveFormat {
+        &self.format
+    }
+
+    pub fn spec(&self) -> WaveSpec {
+        self.format.spec()
+    }
+
+    pub fn data_chunk(&self) -> &Chunk {
+        &self.data_chunk
+    }
+
+    pub fn skipped_chunks(&self) -> &[Chunk] {
+        &self.skipped_chunks
+    }
+
+    /// Chunks like `LIST` and `bext` which should be written with the samples.
+    pub fn metadata(&self) -> &[MetadataChunk] {
+        &self.metadata
+    }
+
+    /// Number of frames of the whole `data` chunk.
+    pub fn duration(&self) -> usize {
+        let block_align = self.format.block_align.max(1);
+        match self.format.frames_per_block() {
+            1 => self.data_chunk.size / block_align,
+            // the last block can be incomplete
+            frames_per_block => {
+                let frames = self.data_chunk.size.div_ceil(block_align) * frames_per_block;
+                self.fact_sample_frames
+                    .map_or(frames, |fact| fact.min(frames))
+            }
+        }
+    }
+
+    /// Decode blocks until `pending` has `wanted` samples or `data` chunk ends.
+    fn fill_pending(&mut self, wanted: usize) -> Result<()> {
+        let channels = self.format.number_of_channels;
+        while self.pending.len() < wanted && self.remaining > 0 {
+            // whole blocks which have the missing frames
+            let missing_frames = (wanted - self.pending.len()).div_ceil(channels);
+            let blocks = missing_frames
+                .max(MIN_READ_FRAMES)
+                .div_ceil(self.format.frames_per_block());
+            let size = (blocks * self.format.block_align.max(1)).min(self.remaining);
+            let mut raw_chunk_data = vec![0; size];
+            let read = read_fully(&mut self.reader, &mut raw_chunk_data)?;
+            raw_chunk_data.truncate(read);
+            // a stream may end before the size of `data` chunk
+            self.remaining = if read < size {
+                0
+            } else {
+                self.remaining - size
+            };
+
+            let mut samples = self.format.decode(&raw_chunk_data, self.frames_left)?;
+            if let Some(frames_left) = self.frames_left.as_mut() {
+                samples.truncate(*frames_left * channels);
+                *frames_left -= samples.len() / channels;
+            }
+            self.pending.extend(samples);
+        }
+
+        Ok(())
+    }
+
+    /// Read at most `frames` frames as samples of each channel, they are empty at the end.
+    pub fn read_frames(&mut self, frames: usize) -> Result<Vec<Vec<f64>>> {
+        let channels = self.format.number_of_channels;
+        let wanted = frames * channels;
+        self.fill_pending(wanted)?;
+
+        // an incomplete last frame is dropped
+        let complete = self.pending.len() - self.pending.len() % channels.max(1);
+        let samples: Vec<f64> = self.pending.drain(..wanted.min(complete)).collect();
+
+        Ok(deinterleave(&samples, channels))
+    }
+
+    /// Iterator over frames, each one has a sample of each channel.
+    pub fn frames(&mut self) -> Frames<'_, R> {
+        Frames { reader: self }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Iterator over frames of `WaveReader`.
+#[derive(Debug)]
+pub struct Frames<'a, R> {
+    reader: &'a mut WaveReader<R>,
+}
+
+impl<'a, R: Read> Iterator for Frames<'a, R> {
+    type Item = Result<Vec<f64>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // blocks are decoded only when the ones before run out
+        let channels = self.reader.format.number_of_channels;
+        if let Err(error) = self.reader.fill_pending(channels) {
+            return Some(Err(error));
+        }
+        if self.reader.pending.len() < channels {
+            return None;
+        }
+
+        Some(Ok(self.reader.pending.drain(..channels).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize::SampleFormat;
+    use crate::read::parse_wave;
+    use crate::write::{encode_samples, interleave, wave_header};
+    use std::io::Cursor;
+
+    fn stereo_wave() -> Vec<u8> {
+        let spec = WaveSpec {
+            channels: 2,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 16,
+        };
+        let buffer: Vec<Vec<f64>> = (0..2)
+            .map(|c| {
+                (0..10000)
+                    .map(|i| ((i + c * 7) as f64 * 0.01).sin() * 0.5)
+                    .collect()
+            })
+            .collect();
+        let bytes = encode_samples(&interleave(&buffer), spec.sample_format, 16).unwrap();
+        let mut wave = wave_header(&spec, bytes.len(), 0);
+        wave.extend(bytes);
+
+        wave
+    }
+
+    #[test]
+    fn read_frames_in_blocks() {
+        let wave = stereo_wave();
+        let expected = parse_wave(&wave).unwrap().normalized_sample_data;
+
+        let mut reader = WaveReader::new(Cursor::new(wave)).unwrap();
+        assert_eq!(10000, reader.duration());
+        assert_eq!(2, reader.spec().channels);
+        let mut result = vec![Vec::new(); 2];
+        loop {
+            let block = reader.read_frames(3000).unwrap();
+            if block[0].is_empty() {
+                break;
+            }
+            assert!(block[0].len() <= 3000);
+            for (channel, samples) in result.iter_mut().zip(block) {
+                channel.extend(samples);
+            }
+        }
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn iterate_frames_of_format_after_data() {
+        let wave = stereo_wave();
+        let expected = parse_wave(&wave).unwrap().normalized_sample_data;
+        // move `fmt ` chunk following `JUNK` chunk after `data` chunk
+        let mut moved = wave[0..48].to_vec();
+        moved.extend(&wave[72..]);
+        moved.extend(&wave[48..72]);
+
+        let mut reader = WaveReader::new(Cursor::new(moved)).unwrap();
+        let frames: Vec<Vec<f64>> = reader.frames().map(|frame| frame.unwrap()).collect();
+        assert_eq!(10000, frames.len());
+        assert_eq!(expected[0][1234], frames[1234][0]);
+        assert_eq!(expected[1][9999], frames[9999][1]);
+    }
+
+    #[test]
+    fn read_rf64_sizes_of_ds64() {
+        let wave = stereo_wave();
+        let expected = parse_wave(&wave).unwrap().normalized_sample_data;
+        // replace sizes with the ones of `ds64` chunk which the writer reserves with `JUNK` chunk
+        let mut rf64 = wave.clone();
+        let ds64 = Ds64 {
+            riff_size: wave.len() as u64 - 8,
+            data_size: 40000,
+            sample_count: 10000,
+            table: Vec::new(),
+        };
+        rf64[0..8].copy_from_slice(b"BW64\xff\xff\xff\xff");
+        rf64[12..16].copy_from_slice(b"ds64");
+        rf64[20..48].copy_from_slice(&ds64.to_bytes());
+        rf64[76..80].copy_from_slice(&RF64_SIZE.to_le_bytes());
+
+        let parsed = parse_wave(&rf64).unwrap();
+        assert_eq!(wave.len(), parsed.file_size);
+        assert_eq!(expected, parsed.normalized_sample_data);
+        assert!(parsed.metadata.is_empty());
+        let mut reader = WaveReader::new(Cursor::new(rf64.clone())).unwrap();
+        assert_eq!(40000, reader.data_chunk().size);
+        assert_eq!(expected, reader.read_frames(20000).unwrap());
+
+        // hostile size beyond the file and the address space
+        let mut hostile = rf64.clone();
+        hostile[28..36].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            parse_wave(&hostile),
+            Err(Error::DataExceedsFile { .. })
+        ));
+        assert!(matches!(
+            WaveReader::new(Cursor::new(hostile.clone())),
+            Err(Error::DataExceedsFile { .. })
+        ));
+        let mut reader = WaveReader::from_stream(hostile.as_slice()).unwrap();
+        assert_eq!(expected, reader.read_frames(20000).unwrap());
+
+        // sizes are unknown without `ds64` chunk
+        rf64[12..16].copy_from_slice(b"JUNK");
+        assert!(matches!(
+            WaveReader::new(Cursor::new(rf64)),
+            Err(Error::MissingChunk("ds64"))
+        ));
+    }
+
+    #[test]
+    fn read_stream_of_unknown_size() {
+        let mut wave = stereo_wave();
+        let expected = parse_wave(&wave).unwrap().normalized_sample_data;
+        // placeholders of RIFF and `data` chunk written to a pipe
+        wave[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        wave[76..80].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            WaveReader::new(Cursor::new(wave.clone())),
+            Err(Error::DataExceedsFile { .. })
+        ));
+        let mut zero = wave.clone();
+        zero[76..80].copy_from_slice(&0_u32.to_le_bytes());
+
+        // slices are read forward only
+        for stream in [&wave, &zero] {
+            let mut reader = WaveReader::from_stream(stream.as_slice()).unwrap();
+            assert_eq!(0, reader.duration());
+            assert_eq!(expected, reader.read_frames(20000).unwrap());
+            assert!(reader.read_frames(1).unwrap()[0].is_empty());
+        }
+
+        // a stream of the known size ends there, and one shorter than it ends at its end
+        let mut wave = stereo_wave();
+        wave.extend(b"LIST\x04\x00\x00\x00INFO");
+        let mut reader = WaveReader::from_stream(wave.as_slice()).unwrap();
+        assert_eq!(10000, reader.duration());
+        assert_eq!(expected, reader.read_frames(20000).unwrap());
+        assert!(reader.metadata().is_empty());
+        let mut reader = WaveReader::from_stream(&wave[..wave.len() - 12 - 4000]).unwrap();
+        assert_eq!(9000, reader.read_frames(20000).unwrap()[0].len());
+    }
+
+    #[test]
+    fn read_ima_adpcm_frames_up_to_fact() {
+        let path = std::env::temp_dir().join("phase-gradient-vocoder-reader-ima-adpcm.wav");
+        let buffer: Vec<f64> = (0..5000).map(|i| (i as f64 * 0.01).sin() * 0.5).collect();
+        crate::write::wav_write_ima_adpcm(path.to_str().unwrap(), vec![buffer], 8000, &[]).unwrap();
+        let wave = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let expected = parse_wave(&wave).unwrap().normalized_sample_data;
+
+        let mut reader = WaveReader::new(Cursor::new(wave)).unwrap();
+        assert_eq!(5000, reader.duration());
+        let mut result = Vec::new();
+        loop {
+            let block = reader.read_frames(777).unwrap();
+            if block[0].is_empty() {
+                break;
+            }
+            result.extend(block[0].iter());
+        }
+        assert_eq!(expected[0], result);
+    }
+}
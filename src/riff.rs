@@ -0,0 +1,206 @@
+/// Chunk of RIFF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// four character code like `fmt ` or `data`
+    pub id: [u8; 4],
+    /// byte offset of the chunk data from the beginning of file
+    pub offset: usize,
+    /// size of the chunk data excluding the pad byte
+    pub size: usize,
+}
+
+impl Chunk {
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.id).to_string()
+    }
+
+    /// Chunk data is followed by a pad byte if its size is odd.
+    /// It saturates for sizes of `ds64` chunk which exceed the address space.
+    pub fn padded_size(&self) -> usize {
+        self.size.saturating_add(self.size % 2)
+    }
+
+    /// Chunk data in `buf`, truncated if the file ends before it.
+    pub fn data<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        let start = self.offset.min(buf.len());
+        let end = self.offset.saturating_add(self.size).min(buf.len());
+        &buf[start..end]
+    }
+}
+
+/// Size field of chunks whose size is given by `ds64` chunk of RF64.
+pub const RF64_SIZE: u32 = u32::MAX;
+
+/// RF64 of EBU Tech 3306 and BW64 of ITU-R BS.2088 have 64-bit sizes in `ds64` chunk.
+pub fn is_rf64(magic: &[u8]) -> bool {
+    magic == b"RF64" || magic == b"BW64"
+}
+
+/// `ds64` chunk, which is the first one of RF64 and BW64.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ds64 {
+    pub riff_size: u64,
+    pub data_size: u64,
+    /// number of sample frames, which replaces the one of `fact` chunk
+    pub sample_count: u64,
+    /// sizes of other chunks than `data`
+    pub table: Vec<([u8; 4], u64)>,
+}
+
+impl Ds64 {
+    /// Parse chunk data, which has 28 bytes followed by the table.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let u64_at = |at: usize| Some(u64::from_le_bytes(data.get(at..at + 8)?.try_into().ok()?));
+        let table_length = u32::from_le_bytes(data.get(24..28)?.try_into().ok()?) as usize;
+        let table = data[28..]
+            .chunks_exact(12)
+            .take(table_length)
+            .map(|entry| {
+                let id = [entry[0], entry[1], entry[2], entry[3]];
+                (id, u64::from_le_bytes(entry[4..12].try_into().unwrap()))
+            })
+            .collect();
+
+        Some(Ds64 {
+            riff_size: u64_at(0)?,
+            data_size: u64_at(8)?,
+            sample_count: u64_at(16)?,
+            table,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(28 + self.table.len() * 12);
+        bytes.extend_from_slice(&self.riff_size.to_le_bytes());
+        bytes.extend_from_slice(&self.data_size.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_count.to_le_bytes());
+        bytes.extend_from_slice(&(self.table.len() as u32).to_le_bytes());
+        for (id, size) in self.table.iter() {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Size of the chunk of `id`, which is replaced by this chunk if its size field is `RF64_SIZE`.
+    pub fn chunk_size(&self, id: &[u8; 4], size: usize) -> usize {
+        if size != RF64_SIZE as usize {
+            return size;
+        }
+        let found = if id == b"data" {
+            Some(self.data_size)
+        } else {
+            self.table
+                .iter()
+                .find(|(table_id, _)| table_id == id)
+                .map(|(_, size)| *size)
+        };
+        found.map_or(size, |size| usize::try_from(size).unwrap_or(usize::MAX))
+    }
+}
+
+/// Iterator over chunks which follow each other from `position`.
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    buf: &'a [u8],
+    position: usize,
+    /// 64-bit sizes of RF64
+    ds64: Option<Ds64>,
+}
+
+impl<'a> Chunks<'a> {
+    pub fn new(buf: &'a [u8], position: usize) -> Self {
+        Chunks {
+            buf,
+            position,
+            ds64: None,
+        }
+    }
+
+    /// Chunks whose sizes are given by `ds64` if their size fields are `RF64_SIZE`.
+    pub fn with_ds64(mut self, ds64: Ds64) -> Self {
+        self.ds64 = Some(ds64);
+        self
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        // chunk ID and chunk data size
+        let header = self.buf.get(self.position..self.position.checked_add(8)?)?;
+        let id = [header[0], header[1], header[2], header[3]];
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let size = match &self.ds64 {
+            Some(ds64) => ds64.chunk_size(&id, size),
+            None => size,
+        };
+        let chunk = Chunk {
+            id,
+            offset: self.position + 8,
+            size,
+        };
+        // the walk stops after a chunk which claims more bytes than the address space
+        self.position = chunk.offset.saturating_add(chunk.padded_size());
+
+        Some(chunk)
+    }
+}
+
+/// Chunks in the RIFF chunk of `buf`, following RIFF header.
+/// Sizes of RF64 are given by its first `ds64` chunk.
+pub fn riff_chunks(buf: &[u8]) -> Chunks<'_> {
+    let chunks = Chunks::new(buf, 12);
+    match rf64_ds64(buf) {
+        Some(ds64) => chunks.with_ds64(ds64),
+        None => chunks,
+    }
+}
+
+/// `ds64` chunk of RF64 in `buf`, which is the whole file or its header.
+pub fn rf64_ds64(buf: &[u8]) -> Option<Ds64> {
+    if !is_rf64(buf.get(0..4)?) || buf.get(12..16)? != b"ds64" {
+        return None;
+    }
+    let chunk = Chunks::new(buf, 12).next()?;
+    Ds64::parse(chunk.data(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn walk_padded_chunks() {
+        let mut buf = b"RIFF\x00\x00\x00\x00WAVE".to_vec();
+        buf.extend(b"odd \x03\x00\x00\x00abc\x00");
+        buf.extend(b"even\x02\x00\x00\x00de");
+        buf.extend(b"data\x10\x00\x00\x00fg");
+
+        let chunks: Vec<Chunk> = riff_chunks(&buf).collect();
+        assert_eq!(
+            vec!["odd ", "even", "data"],
+            chunks.iter().map(|c| c.name()).collect::<Vec<_>>()
+        );
+        assert_eq!(b"abc", chunks[0].data(&buf));
+        assert_eq!(b"de", chunks[1].data(&buf));
+        // truncated
+        assert_eq!(b"fg", chunks[2].data(&buf));
+    }
+
+    #[test]
+    fn stop_at_hostile_ds64_size() {
+        let ds64 = Ds64 {
+            table: vec![(*b"JUNK", u64::MAX)],
+            ..Default::default()
+        };
+        let mut buf = b"RF64\xff\xff\xff\xffWAVE".to_vec();
+        buf.extend(b"JUNK\xff\xff\xff\xffabcd");
+        buf.extend(b"data\x02\x00\x00\x00de");
+
+        let chunks: Vec<Chunk> = Chunks::new(&buf, 12).with_ds64(ds64).collect();
+        assert_eq!(1, chunks.len());
+        assert_eq!(usize::MAX, chunks[0].padded_size());
+        assert_eq!(b"abcddata\x02\x00\x00\x00de", chunks[0].data(&buf));
+    }
+}
@@ -0,0 +1,250 @@
+use clap::ValueEnum;
+
+use crate::read::u32_at;
+use crate::vocoder::{PhaseGradientVocoder, VocoderResult};
+
+/// Offset of the number of loops in `smpl` chunk, following MIDI unity note, SMPTE offset and so on.
+const SMPL_NUMBER_OF_LOOPS: usize = 28;
+/// Offset of the first loop in `smpl` chunk.
+const SMPL_LOOPS: usize = 36;
+/// Bytes of a loop.
+const LOOP_SIZE: usize = 24;
+
+/// Loop of `smpl` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleLoop {
+    /// cue point of the loop
+    pub id: u32,
+    /// 0 is forward, 1 is alternating and 2 is backward
+    pub loop_type: u32,
+    /// first frame of the loop
+    pub start: usize,
+    /// last frame of the loop, which is played
+    pub end: usize,
+    pub play_count: u32,
+}
+
+impl SampleLoop {
+    /// Number of frames of the loop.
+    pub fn len(&self) -> usize {
+        self.end + 1 - self.start.min(self.end + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Offsets of loops in `smpl` chunk data.
+fn loop_offsets(data: &[u8]) -> impl Iterator<Item = usize> {
+    let count = if data.len() >= SMPL_LOOPS {
+        u32_at(data, SMPL_NUMBER_OF_LOOPS)
+    } else {
+        0
+    };
+    let available = data.len().saturating_sub(SMPL_LOOPS) / LOOP_SIZE;
+    (0..count.min(available)).map(|i| SMPL_LOOPS + i * LOOP_SIZE)
+}
+
+/// Loops of `smpl` chunk data.
+pub fn sample_loops(data: &[u8]) -> Vec<SampleLoop> {
+    loop_offsets(data)
+        .map(|at| SampleLoop {
+            id: u32_at(data, at) as u32,
+            loop_type: u32_at(data, at + 4) as u32,
+            start: u32_at(data, at + 8),
+            end: u32_at(data, at + 12),
+            play_count: u32_at(data, at + 20) as u32,
+        })
+        .collect()
+}
+
+/// Move loops of `smpl` chunk data by `map`, the frame after the end of each loop is moved as well
+/// so that its length follows the map.
+pub fn remap_loops(data: &mut [u8], map: impl Fn(u64) -> u64) {
+    for at in loop_offsets(data).collect::<Vec<_>>() {
+        let start = u32_at(data, at + 8) as u64;
+        let end = u32_at(data, at + 12) as u64;
+        let mapped_start = map(start);
+        let mapped_end = map(end + 1).saturating_sub(1).max(mapped_start);
+        for (at, value) in [(at + 8, mapped_start), (at + 12, mapped_end)] {
+            let value = value.min(u32::MAX as u64) as u32;
+            data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+/// How loop regions are stretched.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum LoopStretch {
+    /// loops are stretched with the rest of the input and only their points are moved
+    Linear,
+    /// loops are stretched as repeated ones, so that their end continues to their start
+    Circular,
+}
+
+/// Replace loop regions of `output` with the ones stretched circularly.
+/// `input` is the whole input of the vocoder and `output` is its whole output, loops are in input frames.
+pub fn stretch_loops_circularly(
+    vocoder: &PhaseGradientVocoder,
+    input: &[Vec<f64>],
+    output: &mut [Vec<f64>],
+    loops: &[SampleLoop],
+) -> VocoderResult<()> {
+    let frame_size = vocoder.config().frame_size;
+    let input_len = input.first().map_or(0, |channel| channel.len());
+    let output_len = output.first().map_or(0, |channel| channel.len());
+    for sample_loop in loops {
+        if sample_loop.is_empty() || sample_loop.end >= input_len {
+            continue;
+        }
+        let start = vocoder.map_position(sample_loop.start).min(output_len);
+        let end = vocoder.map_position(sample_loop.end + 1).min(output_len);
+        let length = end.saturating_sub(start);
+        if length < 2 {
+            continue;
+        }
+
+        // repeat the loop enough times for frames around the middle one
+        let repeats = (2 * frame_size).div_ceil(sample_loop.len()) + 1;
+        let tiled: Vec<Vec<f64>> = input
+            .iter()
+            .map(|channel| {
+                let region = &channel[sample_loop.start..=sample_loop.end];
+                region.repeat(2 * repeats + 1)
+            })
+            .collect();
+        let stretched = vocoder.process_channels(&tiled)?;
+        let middle = vocoder.map_position(repeats * sample_loop.len());
+
+        let fade = (length / 2).min(frame_size);
+        // the output before the start and after the end may be shorter than the fade
+        let edge_fade = fade.min(start).min(output_len - end);
+        for (channel, stretched) in output.iter_mut().zip(stretched.iter()) {
+            let circular = |i: isize| stretched[(middle as isize + i) as usize];
+            // the end of the loop fades into the frames before its start, which continue to the start
+            let mut region: Vec<f64> = (0..length as isize).map(circular).collect();
+            for i in 0..fade {
+                let k = length - fade + i;
+                let weight = (i + 1) as f64 / fade as f64;
+                region[k] =
+                    (1.0 - weight) * region[k] + weight * circular(k as isize - length as isize);
+            }
+
+            // cross-fades from the linear output into the loop and back, which have the same length
+            for i in 0..edge_fade {
+                let k = start - edge_fade + i;
+                let weight = (i + 1) as f64 / (edge_fade + 1) as f64;
+                channel[k] =
+                    (1.0 - weight) * channel[k] + weight * circular(k as isize - start as isize);
+            }
+            channel[start..end].copy_from_slice(&region);
+            for i in 0..edge_fade {
+                let weight = (i + 1) as f64 / (edge_fade + 1) as f64;
+                channel[end + i] =
+                    weight * channel[end + i] + (1.0 - weight) * circular(i as isize);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vocoder::{Mode, VocoderConfig};
+
+    fn smpl(loops: &[(usize, usize)]) -> Vec<u8> {
+        let mut data = vec![0; SMPL_NUMBER_OF_LOOPS];
+        data.extend((loops.len() as u32).to_le_bytes());
+        data.extend(0_u32.to_le_bytes());
+        for (i, (start, end)) in loops.iter().enumerate() {
+            for value in [i as u32, 0, *start as u32, *end as u32, 0, 0] {
+                data.extend(value.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn remap_sample_loops() {
+        let mut data = smpl(&[(100, 199), (1000, 1999)]);
+        remap_loops(&mut data, |position| position * 3 / 2);
+        let loops = sample_loops(&data);
+        assert_eq!(2, loops.len());
+        assert_eq!(
+            (150, 299, 150),
+            (loops[0].start, loops[0].end, loops[0].len())
+        );
+        assert_eq!((1500, 2999), (loops[1].start, loops[1].end));
+        // truncated loops are ignored
+        assert_eq!(1, sample_loops(&data[..SMPL_LOOPS + LOOP_SIZE + 10]).len());
+    }
+
+    #[test]
+    fn seamless_circular_loop() {
+        // the loop has a whole number of periods
+        let input: Vec<f64> = (0..20000)
+            .map(|i| (i as f64 * 2.0 * std::f64::consts::PI / 100.0).sin() * 0.5)
+            .collect();
+        let config = VocoderConfig {
+            seed: Some(3),
+            ..VocoderConfig::new(Mode::TimeStretch, 1.37).with_frame_size(1024)
+        };
+        let vocoder = PhaseGradientVocoder::new(config).unwrap();
+        let mut output = vocoder
+            .process_channels(std::slice::from_ref(&input))
+            .unwrap();
+        let sample_loop = SampleLoop {
+            id: 0,
+            loop_type: 0,
+            start: 8000,
+            end: 11999,
+            play_count: 0,
+        };
+        stretch_loops_circularly(&vocoder, &[input], &mut output, &[sample_loop]).unwrap();
+
+        let start = vocoder.map_position(sample_loop.start);
+        let end = vocoder.map_position(sample_loop.end + 1);
+        let output = &output[0];
+        // the step from the end of the loop to its start is as small as the ones inside it
+        let max_step = output[start..end]
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f64::max);
+        assert!((output[start] - output[end - 1]).abs() <= max_step * 1.1);
+    }
+
+    #[test]
+    fn symmetric_crossfade_of_early_loop() {
+        let input: Vec<f64> = (0..20000)
+            .map(|i| (i as f64 * 2.0 * std::f64::consts::PI / 100.0).sin() * 0.5)
+            .collect();
+        let config = VocoderConfig {
+            seed: Some(3),
+            ..VocoderConfig::new(Mode::TimeStretch, 1.37).with_frame_size(1024)
+        };
+        let vocoder = PhaseGradientVocoder::new(config).unwrap();
+        let linear = vocoder
+            .process_channels(std::slice::from_ref(&input))
+            .unwrap();
+        let mut output = linear.clone();
+        // the loop starts earlier than the fade length
+        let sample_loop = SampleLoop {
+            id: 0,
+            loop_type: 0,
+            start: 600,
+            end: 4599,
+            play_count: 0,
+        };
+        stretch_loops_circularly(&vocoder, &[input], &mut output, &[sample_loop]).unwrap();
+
+        let start = vocoder.map_position(sample_loop.start);
+        let end = vocoder.map_position(sample_loop.end + 1);
+        assert!(0 < start && start < 1024);
+        // the fade back into the linear output is as short as the one into the loop
+        assert_ne!(linear[0][end..end + start], output[0][end..end + start]);
+        assert_eq!(linear[0][end + start..], output[0][end + start..]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+
+use crate::normalize::SampleFormat;
+
+/// Compression code of `fmt ` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Pcm,
+    MsAdpcm,
+    IeeeFloat,
+    IbmCsvd,
+    ALaw,
+    MuLaw,
+    ImaAdpcm,
+    Extensible,
+    Other(u16),
+}
+
+impl Codec {
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            1 => Codec::Pcm,
+            2 => Codec::MsAdpcm,
+            3 => Codec::IeeeFloat,
+            5 => Codec::IbmCsvd,
+            6 => Codec::ALaw,
+            7 => Codec::MuLaw,
+            0x11 => Codec::ImaAdpcm,
+            0xFFFE => Codec::Extensible,
+            code => Codec::Other(code),
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            Codec::Pcm => 1,
+            Codec::MsAdpcm => 2,
+            Codec::IeeeFloat => 3,
+            Codec::IbmCsvd => 5,
+            Codec::ALaw => 6,
+            Codec::MuLaw => 7,
+            Codec::ImaAdpcm => 0x11,
+            Codec::Extensible => 0xFFFE,
+            Codec::Other(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Codec::Pcm => write!(f, "Linear PCM"),
+            Codec::MsAdpcm => write!(f, "MS-ADPCM"),
+            Codec::IeeeFloat => write!(f, "IEEE float"),
+            Codec::IbmCsvd => write!(f, "IBM CSVD"),
+            Codec::ALaw => write!(f, "A-law"),
+            Codec::MuLaw => write!(f, "mu-law"),
+            Codec::ImaAdpcm => write!(f, "IMA ADPCM"),
+            Codec::Extensible => write!(f, "Extensible"),
+            Codec::Other(code) => write!(f, "Unknown wave format {:#06x}", code),
+        }
+    }
+}
+
+/// Layout of channels by their number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Monaural,
+    Stereo,
+    Multichannel(usize),
+}
+
+impl ChannelLayout {
+    pub fn from_count(channels: usize) -> Self {
+        match channels {
+            1 => ChannelLayout::Monaural,
+            2 => ChannelLayout::Stereo,
+            n => ChannelLayout::Multichannel(n),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match self {
+            ChannelLayout::Monaural => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Multichannel(n) => *n,
+        }
+    }
+}
+
+impl fmt::Display for ChannelLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelLayout::Monaural => write!(f, "Monaural"),
+            ChannelLayout::Stereo => write!(f, "Stereo"),
+            ChannelLayout::Multichannel(n) => write!(f, "{} channels", n),
+        }
+    }
+}
+
+/// Format of linear PCM or IEEE float samples, which is enough to write a wave file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaveSpec {
+    pub channels: usize,
+    pub sample_rate: usize,
+    pub sample_format: SampleFormat,
+    pub bits_per_sample: usize,
+}
+
+impl WaveSpec {
+    /// Bytes of a frame.
+    pub fn block_align(&self) -> usize {
+        self.channels * self.bits_per_sample / 8
+    }
+
+    pub fn bytes_per_second(&self) -> usize {
+        self.sample_rate * self.block_align()
+    }
+
+    /// Compression code, WAVE_FORMAT_EXTENSIBLE is not considered.
+    pub fn codec(&self) -> Codec {
+        match self.sample_format {
+            SampleFormat::Int => Codec::Pcm,
+            SampleFormat::Float => Codec::IeeeFloat,
+        }
+    }
+
+    /// WAVE_FORMAT_EXTENSIBLE is needed for more than two channels or more than 16 bits.
+    pub fn needs_extensible(&self) -> bool {
+        self.channels > 2 || self.bits_per_sample > 16
+    }
+}
@@ -0,0 +1,438 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::VecDeque;
+
+use crate::process::principal_argument;
+use crate::vocoder::{
+    frequency_delta_phi, integrate_phase, ChannelLink, PhaseGradient, PhaseGradientVocoder, Scheme,
+};
+
+/// Polar form of an analysed frame.
+#[derive(Debug, Clone)]
+struct Frame {
+    magnitude: Vec<f64>,
+    phase: Vec<f64>,
+}
+
+/// Frames analysed at the same time.
+#[derive(Debug, Clone)]
+struct AnalysedFrame {
+    // Frames whose phase is integrated, one for each channel or a shared one for linked channels.
+    tracks: Vec<Frame>,
+    // Frames of each channel when the channels are linked.
+    channels: Vec<Frame>,
+}
+
+/// Vocoder which processes the input block by block in bounded memory.
+///
+/// Only the frames the differentiation scheme needs (n − 2 to n + 1 at most) are kept,
+/// and output samples are returned as soon as no later frame overlaps them.
+#[derive(Debug, Clone)]
+pub struct StreamingVocoder {
+    vocoder: PhaseGradientVocoder,
+    rng: StdRng,
+    // Input samples of each channel which have not been consumed by the analysis yet, `input[c][0]` is sample `input_offset`.
+    input: Vec<Vec<f64>>,
+    input_offset: usize,
+    input_len: usize,
+    // Analysed frames from `analysed - frames.len()` to `analysed - 1`.
+    frames: VecDeque<AnalysedFrame>,
+    analysed: usize,
+    synthesized: usize,
+    // φs(·,n − 1) of each track
+    previous_phase: Vec<Vec<f64>>,
+    // Overlap-added samples of each channel which are not returned yet, `output[c][0]` is sample `output_offset`.
+    output: Vec<Vec<f64>>,
+    output_offset: usize,
+}
+
+impl StreamingVocoder {
+    /// `channels` must be 2 for mid/side processing, which `PhaseGradientVocoder::stream_channels` checks.
+    pub(crate) fn new(vocoder: PhaseGradientVocoder, channels: usize) -> Self {
+        let rng = match vocoder.config().seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let tracks = match vocoder.config().link {
+            ChannelLink::Independent => channels,
+            ChannelLink::Linked | ChannelLink::MidSide => 1,
+        };
+
+        StreamingVocoder {
+            vocoder,
+            rng,
+            input: vec![Vec::new(); channels],
+            input_offset: 0,
+            input_len: 0,
+            frames: VecDeque::new(),
+            analysed: 0,
+            synthesized: 0,
+            previous_phase: vec![Vec::new(); tracks],
+            output: vec![Vec::new(); channels],
+            output_offset: 0,
+        }
+    }
+
+    pub fn vocoder(&self) -> &PhaseGradientVocoder {
+        &self.vocoder
+    }
+
+    pub fn channels(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Feed the next block of monaural input and return the output samples completed by it.
+    pub fn push(&mut self, input: &[f64]) -> Vec<f64> {
+        self.push_channels(&[input]).swap_remove(0)
+    }
+
+    /// Process the rest of frames of monaural input and return the remaining output samples.
+    pub fn finish(self) -> Vec<f64> {
+        self.finish_channels().swap_remove(0)
+    }
+
+    /// Feed the next block of each channel and return the output samples completed by it.
+    /// Every channel must have the same number of samples.
+    pub fn push_channels<T: AsRef<[f64]>>(&mut self, input: &[T]) -> Vec<Vec<f64>> {
+        assert_eq!(self.channels(), input.len(), "number of channels differs");
+        let length = input.first().map_or(0, |channel| channel.as_ref().len());
+        assert!(
+            input.iter().all(|channel| channel.as_ref().len() == length),
+            "length of channels differs"
+        );
+
+        if self.vocoder.config().link == ChannelLink::MidSide {
+            let (left, right) = (input[0].as_ref(), input[1].as_ref());
+            self.input[0].extend(left.iter().zip(right).map(|(l, r)| (l + r) / 2.0));
+            self.input[1].extend(left.iter().zip(right).map(|(l, r)| (l - r) / 2.0));
+        } else {
+            for (buffer, channel) in self.input.iter_mut().zip(input.iter()) {
+                buffer.extend_from_slice(channel.as_ref());
+            }
+        }
+        self.input_len += length;
+
+        let frame_size = self.vocoder.config().frame_size;
+        let analysis_hopsize = self.vocoder.analysis_hopsize();
+        loop {
+            let offset = analysis_hopsize * self.analysed;
+            // The frame has to exist regardless of the rest of the input, and be filled entirely.
+            if offset + analysis_hopsize > self.input_len || offset + frame_size > self.input_len {
+                break;
+            }
+            self.analyse_next();
+            // The centered differentiation scheme needs the next frame.
+            if self.synthesized + self.vocoder.config().scheme.lookahead() < self.analysed {
+                self.synthesize_next(false);
+            }
+        }
+
+        // No later frame overlaps the samples before the next one.
+        // The total length is unknown, but it can not be shorter than the one of the input so far.
+        let limit = (self.vocoder.output_hopsize() * self.synthesized)
+            .min(self.vocoder.output_len(self.input_len));
+        self.drain_output(limit)
+    }
+
+    /// Process the rest of frames on the end of input and return the remaining output samples of each channel.
+    pub fn finish_channels(mut self) -> Vec<Vec<f64>> {
+        let number_of_frame = self.input_len / self.vocoder.analysis_hopsize();
+        while self.analysed < number_of_frame {
+            self.analyse_next();
+        }
+        while self.synthesized < number_of_frame {
+            let last = self.synthesized + 1 >= number_of_frame;
+            self.synthesize_next(last);
+        }
+
+        let result_len = self.vocoder.output_len(self.input_len);
+        for output in self.output.iter_mut() {
+            if self.output_offset + output.len() < result_len {
+                output.resize(result_len - self.output_offset, 0.0);
+            }
+        }
+        self.drain_output(result_len)
+    }
+
+    fn analyse_next(&mut self) {
+        let offset = self.vocoder.analysis_hopsize() * self.analysed;
+        let channels: Vec<Frame> = self
+            .input
+            .iter()
+            .map(|input| {
+                let (magnitude, phase) = self.vocoder.analysis(input, offset - self.input_offset);
+                Frame { magnitude, phase }
+            })
+            .collect();
+        let frame = match self.vocoder.config().link {
+            ChannelLink::Independent => AnalysedFrame {
+                tracks: channels,
+                channels: Vec::new(),
+            },
+            link => AnalysedFrame {
+                tracks: vec![linked_frame(&channels, link)],
+                channels,
+            },
+        };
+        self.frames.push_back(frame);
+        self.analysed += 1;
+
+        // Samples before the next frame are not needed anymore.
+        let consumed =
+            (offset + self.vocoder.analysis_hopsize()).min(self.input_len) - self.input_offset;
+        for input in self.input.iter_mut() {
+            input.drain(..consumed.min(input.len()));
+        }
+        self.input_offset += consumed;
+    }
+
+    fn synthesize_next(&mut self, last: bool) {
+        let i = self.synthesized;
+        let frame_size = self.vocoder.config().frame_size;
+        let scalling_factor =
+            self.vocoder.config().hop_size as f64 / self.vocoder.analysis_hopsize() as f64;
+        let (frames, analysed) = (&self.frames, self.analysed);
+        let analysed_frame = |index: usize| &frames[index + frames.len() - analysed];
+
+        let mut alter_phases = Vec::with_capacity(self.previous_phase.len());
+        for (t, previous_phase) in self.previous_phase.iter().enumerate() {
+            let frame = |index: usize| &analysed_frame(index).tracks[t];
+
+            // (∆tφa) (m,n) and (∆fφa) (m,n) are computed for all m and current n
+            let differences = match self.vocoder.config().scheme {
+                _ if i as isize - 2 <= 0 => None,
+                // (∆tφa) (m,n − 1) and (∆tφa) (m,n) with centered differences
+                Scheme::Centered if !last => Some([
+                    (frame(i - 2).phase.as_slice(), frame(i - 1).phase.as_slice()),
+                    (frame(i).phase.as_slice(), frame(i + 1).phase.as_slice()),
+                ]),
+                Scheme::Centered => None,
+                // (∆tφa) (m,n − 1) and (∆tφa) (m,n) with backward differences
+                Scheme::RealTime => Some([
+                    (frame(i - 2).phase.as_slice(), frame(i - 1).phase.as_slice()),
+                    (frame(i - 1).phase.as_slice(), frame(i).phase.as_slice()),
+                ]),
+            };
+            let time_delta_phi = self.vocoder.time_delta_phi(&frame(i).phase, differences);
+            let (frequency_forward_delta_phi, frequency_backward_delta_phi) =
+                frequency_delta_phi(&frame(i).phase, scalling_factor);
+
+            // Return current frame's phase due to there're no the last two frame information until it's third frame.
+            let alter_phase = if i as isize - 1 <= 0 {
+                time_delta_phi
+            } else {
+                integrate_phase(
+                    &PhaseGradient {
+                        magnitude: &frame(i).magnitude,
+                        previous_magnitude: &frame(i - 1).magnitude,
+                        previous_phase,
+                        time_delta_phi: &time_delta_phi,
+                        frequency_forward_delta_phi: &frequency_forward_delta_phi,
+                        frequency_backward_delta_phi: &frequency_backward_delta_phi,
+                    },
+                    i,
+                    self.vocoder.config().tolerance,
+                    &mut self.rng,
+                )
+            };
+            alter_phases.push(alter_phase);
+        }
+
+        // Resynthesis
+        let current = analysed_frame(i);
+        let synthesized_buffers: Vec<Vec<f64>> = if current.channels.is_empty() {
+            current
+                .tracks
+                .iter()
+                .zip(alter_phases.iter())
+                .map(|(frame, alter_phase)| self.vocoder.synthesis(&frame.magnitude, alter_phase))
+                .collect()
+        } else {
+            // Keep the difference of analysis phase between each channel and the shared one.
+            let shared = &current.tracks[0];
+            current
+                .channels
+                .iter()
+                .map(|frame| {
+                    let alter_phase: Vec<f64> = alter_phases[0]
+                        .iter()
+                        .zip(frame.phase.iter().zip(shared.phase.iter()))
+                        .map(|(alter, (phase, shared_phase))| {
+                            alter + principal_argument(phase - shared_phase)
+                        })
+                        .collect();
+                    self.vocoder.synthesis(&frame.magnitude, &alter_phase)
+                })
+                .collect()
+        };
+        let offset = self.vocoder.output_hopsize() * i;
+        for (c, synthesized_buffer) in synthesized_buffers.iter().enumerate() {
+            self.overlap_add(
+                c,
+                offset,
+                &synthesized_buffer[..frame_size.min(synthesized_buffer.len())],
+            );
+        }
+
+        self.previous_phase = alter_phases;
+        self.synthesized += 1;
+
+        // Keep the last two frames for the next one.
+        while self.frames.len() + self.synthesized > self.analysed + 2 {
+            self.frames.pop_front();
+        }
+    }
+
+    fn overlap_add(&mut self, channel: usize, offset: usize, buffer: &[f64]) {
+        let output = &mut self.output[channel];
+        let start = offset - self.output_offset;
+        if output.len() < start + buffer.len() {
+            output.resize(start + buffer.len(), 0.0);
+        }
+        for (y, x) in output[start..].iter_mut().zip(buffer.iter()) {
+            *y += x;
+        }
+    }
+
+    /// Return overlap-added samples before `limit`.
+    fn drain_output(&mut self, limit: usize) -> Vec<Vec<f64>> {
+        let length = self
+            .output
+            .iter()
+            .map(|output| limit.saturating_sub(self.output_offset).min(output.len()))
+            .min()
+            .unwrap_or(0);
+        self.output_offset += length;
+        let mut output: Vec<Vec<f64>> = self
+            .output
+            .iter_mut()
+            .map(|output| output.drain(..length).collect())
+            .collect();
+
+        if self.vocoder.config().link == ChannelLink::MidSide {
+            let (mid, side) = output.split_at_mut(1);
+            for (m, s) in mid[0].iter_mut().zip(side[0].iter_mut()) {
+                (*m, *s) = (*m + *s, *m - *s);
+            }
+        }
+
+        output
+    }
+}
+
+/// Shared frame of linked channels.
+/// Its magnitude is the sum of the ones of each channel, which drives the heap integration.
+fn linked_frame(channels: &[Frame], link: ChannelLink) -> Frame {
+    let fft_size = channels[0].magnitude.len();
+    let magnitude = (0..fft_size)
+        .map(|j| channels.iter().map(|frame| frame.magnitude[j]).sum())
+        .collect();
+    let phase = match link {
+        // The phase of mid channel
+        ChannelLink::MidSide => channels[0].phase.clone(),
+        // The phase of the sum of all channels
+        _ => (0..fft_size)
+            .map(|j| {
+                let (re, im) = channels.iter().fold((0.0, 0.0), |(re, im), frame| {
+                    (
+                        re + frame.magnitude[j] * frame.phase[j].cos(),
+                        im + frame.magnitude[j] * frame.phase[j].sin(),
+                    )
+                });
+                f64::atan2(im, re)
+            })
+            .collect(),
+    };
+
+    Frame { magnitude, phase }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChannelLink, Mode, PhaseGradientVocoder, Scheme, VocoderConfig};
+
+    fn chunked(vocoder: &PhaseGradientVocoder, input: &[f64], chunk_sizes: &[usize]) -> Vec<f64> {
+        let mut stream = vocoder.stream().unwrap();
+        let mut output = Vec::new();
+        let mut rest = input;
+        for chunk_size in chunk_sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, next) = rest.split_at((*chunk_size).min(rest.len()));
+            output.extend(stream.push(chunk));
+            rest = next;
+        }
+        output.extend(stream.finish());
+
+        output
+    }
+
+    #[test]
+    fn identical_regardless_of_chunking() {
+        let input: Vec<f64> = (0..20000)
+            .map(|i| (i as f64 * 0.03).sin() * 0.5 + (i as f64 * 0.0071).cos() * 0.2)
+            .collect();
+
+        for (mode, ratio, scheme) in [
+            (Mode::TimeStretch, 1.7, Scheme::Centered),
+            (Mode::TimeStretch, 0.6, Scheme::Centered),
+            (Mode::PitchShift, 1.3, Scheme::Centered),
+            (Mode::TimeStretch, 1.7, Scheme::RealTime),
+        ] {
+            let config = VocoderConfig {
+                seed: Some(7),
+                scheme,
+                ..VocoderConfig::new(mode, ratio).with_frame_size(512)
+            };
+            let vocoder = PhaseGradientVocoder::new(config).unwrap();
+            let whole = vocoder.process(&input).unwrap();
+            assert_eq!(
+                (input.len() as f64 * vocoder.wave_size_ratio()) as usize,
+                whole.len()
+            );
+
+            for chunk_sizes in [&[1][..], &[333, 17, 4096], &[512], &[100000]] {
+                assert_eq!(whole, chunked(&vocoder, &input, chunk_sizes));
+            }
+        }
+    }
+
+    #[test]
+    fn output_after_latency() {
+        let input: Vec<f64> = (0..4096).map(|i| (i as f64 * 0.03).sin() * 0.5).collect();
+
+        for scheme in [Scheme::Centered, Scheme::RealTime] {
+            let config = VocoderConfig {
+                scheme,
+                ..VocoderConfig::new(Mode::TimeStretch, 1.0).with_frame_size(512)
+            };
+            let vocoder = PhaseGradientVocoder::new(config).unwrap();
+            let latency = vocoder.latency();
+            let mut stream = vocoder.stream().unwrap();
+            assert!(stream.push(&input[..latency - 1]).is_empty());
+            assert!(!stream.push(&input[latency - 1..latency]).is_empty());
+        }
+    }
+
+    #[test]
+    fn linked_channels_keep_identical_channels() {
+        let input: Vec<f64> = (0..8192)
+            .map(|i| (i as f64 * 0.03).sin() * 0.5 + (i as f64 * 0.0071).cos() * 0.2)
+            .collect();
+
+        for link in [ChannelLink::Linked, ChannelLink::MidSide] {
+            let config = VocoderConfig {
+                link,
+                ..VocoderConfig::new(Mode::TimeStretch, 1.3).with_frame_size(512)
+            };
+            let vocoder = PhaseGradientVocoder::new(config).unwrap();
+            let output = vocoder
+                .process_channels(&[input.clone(), input.clone()])
+                .unwrap();
+            for (left, right) in output[0].iter().zip(output[1].iter()) {
+                assert!((left - right).abs() < 1e-9);
+            }
+        }
+    }
+}
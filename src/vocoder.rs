@@ -0,0 +1,591 @@
+use clap::ValueEnum;
+use rand::Rng;
+use std::collections::BinaryHeap;
+use std::f64::consts::PI;
+use thiserror::Error;
+
+use crate::fft::fft;
+use crate::heap::MaxHeap;
+use crate::process::{hanning_window, interpolation, principal_argument};
+use crate::stream::StreamingVocoder;
+
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum Mode {
+    TimeStretch,
+    PitchShift,
+}
+
+/// Differentiation scheme of the time derivative of phase.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum Scheme {
+    /// centered differences, it needs one "future" frame
+    Centered,
+    /// backward differences only (RTPGHI), it has no lookahead
+    RealTime,
+}
+
+impl Scheme {
+    /// Number of frames needed after the current one.
+    pub fn lookahead(&self) -> usize {
+        match self {
+            Scheme::Centered => 1,
+            Scheme::RealTime => 0,
+        }
+    }
+}
+
+/// How the phase of multiple channels is integrated.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum ChannelLink {
+    /// each channel separately
+    Independent,
+    /// once for all channels with the summed magnitude, keeping the phase difference between channels
+    Linked,
+    /// like linked, but stereo channels are processed as mid and side
+    MidSide,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VocoderConfig {
+    /// frame size that should be power of two
+    pub frame_size: usize,
+    /// synthesis hop size
+    pub hop_size: usize,
+    /// weather its time-stretch or pitch-shift
+    pub mode: Mode,
+    /// factor ratio
+    pub ratio: f64,
+    /// differentiation scheme of the time derivative
+    pub scheme: Scheme,
+    /// phase integration of multiple channels
+    pub link: ChannelLink,
+    /// relative tolerance under which bins get random phase
+    pub tolerance: f64,
+    /// seed of random phase, it differs on every run if not given
+    pub seed: Option<u64>,
+}
+
+impl Default for VocoderConfig {
+    fn default() -> Self {
+        VocoderConfig {
+            frame_size: 4096,
+            hop_size: 1024,
+            mode: Mode::TimeStretch,
+            ratio: 1.0,
+            scheme: Scheme::Centered,
+            link: ChannelLink::Independent,
+            tolerance: 10.0_f64.powi(-6),
+            seed: None,
+        }
+    }
+}
+
+impl VocoderConfig {
+    pub fn new(mode: Mode, ratio: f64) -> Self {
+        VocoderConfig {
+            mode,
+            ratio,
+            ..Default::default()
+        }
+    }
+
+    /// Config whose synthesis hop size is a quarter of the frame size.
+    pub fn with_frame_size(self, frame_size: usize) -> Self {
+        VocoderConfig {
+            frame_size,
+            hop_size: frame_size / 4,
+            ..self
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum VocoderError {
+    #[error("frame size {0} is not a power of two")]
+    InvalidFrameSize(usize),
+    #[error("hop size {0} must be between 1 and the frame size")]
+    InvalidHopSize(usize),
+    #[error("ratio {0} must be a positive finite number")]
+    InvalidRatio(f64),
+    #[error("mid/side processing needs 2 channels, but got {0}")]
+    MidSideChannels(usize),
+}
+
+pub type VocoderResult<T> = Result<T, VocoderError>;
+
+#[derive(Debug, Clone)]
+pub struct PhaseGradientVocoder {
+    config: VocoderConfig,
+    analysis_window: Vec<f64>,
+    omega: Vec<f64>,
+}
+
+impl PhaseGradientVocoder {
+    pub fn new(config: VocoderConfig) -> VocoderResult<Self> {
+        if config.frame_size < 2 || !config.frame_size.is_power_of_two() {
+            return Err(VocoderError::InvalidFrameSize(config.frame_size));
+        }
+        if config.hop_size == 0 || config.hop_size > config.frame_size {
+            return Err(VocoderError::InvalidHopSize(config.hop_size));
+        }
+        if !config.ratio.is_finite() || config.ratio <= 0.0 {
+            return Err(VocoderError::InvalidRatio(config.ratio));
+        }
+        // Analysis hop size is rounded, so it must not vanish for large ratio.
+        if (config.hop_size as f64 / config.ratio).round() < 1.0 {
+            return Err(VocoderError::InvalidRatio(config.ratio));
+        }
+
+        let fft_size = 2 * config.frame_size;
+        let analysis_hopsize = (config.hop_size as f64 / config.ratio).round();
+        let omega = (0..fft_size)
+            .map(|i| ((2.0 * PI) * analysis_hopsize * i as f64) / fft_size as f64)
+            .collect();
+
+        Ok(PhaseGradientVocoder {
+            analysis_window: hanning_window(config.frame_size),
+            omega,
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &VocoderConfig {
+        &self.config
+    }
+
+    pub fn analysis_hopsize(&self) -> usize {
+        (self.config.hop_size as f64 / self.config.ratio).round() as usize
+    }
+
+    /// Ratio of output length to input length.
+    pub fn wave_size_ratio(&self) -> f64 {
+        if self.config.mode == Mode::TimeStretch {
+            self.config.ratio
+        } else {
+            1.0
+        }
+    }
+
+    /// Number of output samples between the starting points of consecutive frames.
+    pub fn output_hopsize(&self) -> usize {
+        if self.config.mode == Mode::TimeStretch {
+            self.config.hop_size
+        } else {
+            self.analysis_hopsize()
+        }
+    }
+
+    /// Number of output samples of `input_len` input samples.
+    pub fn output_len(&self, input_len: usize) -> usize {
+        (input_len as f64 * self.wave_size_ratio()) as usize
+    }
+
+    /// Output position where the content at input `position` is synthesized.
+    /// Positions are scaled by the ratio of hop sizes around the frame size, where the integrated phase keeps
+    /// the content of each frame, and frames of pitch-shift are resampled by the ratio afterwards.
+    pub fn map_position(&self, position: usize) -> usize {
+        let output_hopsize = self.output_hopsize() as f64;
+        let scale = output_hopsize / self.analysis_hopsize() as f64;
+        let frame_size = self.config.frame_size as f64;
+        let anchor = frame_size * output_hopsize / self.config.hop_size as f64;
+        ((position as f64 - frame_size) * scale + anchor)
+            .round()
+            .max(0.0) as usize
+    }
+
+    /// Processor which accepts the input block by block and keeps only the frames it still needs.
+    /// Mid/side processing needs 2 channels, so monaural input is rejected by it.
+    pub fn stream(&self) -> VocoderResult<StreamingVocoder> {
+        self.stream_channels(1)
+    }
+
+    /// Processor of multiple channels which accepts the input block by block.
+    pub fn stream_channels(&self, channels: usize) -> VocoderResult<StreamingVocoder> {
+        if self.config.link == ChannelLink::MidSide && channels != 2 {
+            return Err(VocoderError::MidSideChannels(channels));
+        }
+
+        Ok(StreamingVocoder::new(self.clone(), channels))
+    }
+
+    pub fn process(&self, input: &[f64]) -> VocoderResult<Vec<f64>> {
+        let mut stream = self.stream()?;
+        let mut result_buffer = stream.push(input);
+        result_buffer.extend(stream.finish());
+
+        Ok(result_buffer)
+    }
+
+    /// Number of input samples which have to arrive after the beginning of a frame until it is synthesized.
+    pub fn latency(&self) -> usize {
+        match self.config.scheme {
+            Scheme::Centered => self.config.frame_size + self.analysis_hopsize(),
+            Scheme::RealTime => self.config.frame_size,
+        }
+    }
+
+    /// Process each channel, whose phase is integrated as `link` of config.
+    pub fn process_channels(&self, input: &[Vec<f64>]) -> VocoderResult<Vec<Vec<f64>>> {
+        let mut stream = self.stream_channels(input.len())?;
+        let mut result_buffer = stream.push_channels(input);
+        for (buffer, rest) in result_buffer.iter_mut().zip(stream.finish_channels()) {
+            buffer.extend(rest);
+        }
+
+        Ok(result_buffer)
+    }
+
+    /// (∆tφa) (·,n) scaled to synthesis hop size.
+    /// `differences` are pairs of earlier and later analysis phases whose differences are averaged, if they can be used.
+    pub(crate) fn time_delta_phi(
+        &self,
+        phase: &[f64],
+        differences: Option<[(&[f64], &[f64]); 2]>,
+    ) -> Vec<f64> {
+        let fft_size = phase.len();
+        let synthesis_hopsize = self.config.hop_size as f64;
+        let analysis_hopsize = self.analysis_hopsize() as f64;
+
+        // φs(m, n) = φs(m, n − 1) + as / 2 ((∆tφa) (m, n − 1) + (∆tφa) (m, n)) .
+        (0..fft_size)
+            .map(|j| {
+                let bin_frequency = (2.0 * PI * j as f64) / fft_size as f64;
+                match differences {
+                    // It cannot calculate center value, if the vector of the bin placed in the edge of buffer,
+                    // So it should pick the very value.
+                    None => {
+                        synthesis_hopsize
+                            * ((1.0 / analysis_hopsize)
+                                * principal_argument(phase[j] - self.omega[j])
+                                + bin_frequency)
+                    }
+                    Some([(first_earlier, first_later), (second_earlier, second_later)]) => {
+                        synthesis_hopsize / 2.0
+                            * ((1.0 / analysis_hopsize
+                                * principal_argument(
+                                    first_later[j] - first_earlier[j] - self.omega[j],
+                                )
+                                + bin_frequency)
+                                + (1.0 / analysis_hopsize
+                                    * principal_argument(
+                                        second_later[j] - second_earlier[j] - self.omega[j],
+                                    )
+                                    + bin_frequency))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Windowed spectrum of the frame starting at `offset` in polar form.
+    pub(crate) fn analysis(&self, input: &[f64], offset: usize) -> (Vec<f64>, Vec<f64>) {
+        let frame_size = self.config.frame_size;
+        let fft_size = 2 * frame_size;
+        // Zero padding
+        let mut x_real: Vec<f64> = vec![0.0; fft_size];
+        let mut x_imag: Vec<f64> = vec![0.0; fft_size];
+        // Windowning real signal
+        for j in 0..frame_size {
+            if offset + j >= input.len() {
+                break;
+            }
+            x_real[j] = input[offset + j] * self.analysis_window[j];
+        }
+        // Shift signal to center
+        x_real.rotate_right(frame_size);
+        // FFT
+        fft(&mut x_real, &mut x_imag, fft_size, false);
+
+        // In its essence, the method proceeds by pro-cessing one frame at a time computing the synthesis phase of the current n-th frame φs(·,n).
+        // It requires storing the already computed phase φs(·,n −1) and the time derivative (∆tφa) (·,n−1) of the previous (n−1)-th frame and further,
+        // it requires access to the coefficients of the previous, current and one "future" frame (c(·,n−1), c(·,n) and c(·,n+1)) assuming the centered differentiation scheme
+        let magnitude = x_real
+            .iter()
+            .zip(x_imag.iter())
+            .map(|(re, im)| (re * re + im * im).sqrt())
+            .collect();
+        let phase = x_real
+            .iter()
+            .zip(x_imag.iter())
+            .map(|(re, im)| im.atan2(*re))
+            .collect();
+
+        (magnitude, phase)
+    }
+
+    /// Windowed signal of a frame rebuilt from magnitude and synthesis phase.
+    pub(crate) fn synthesis(&self, magnitude: &[f64], alter_phase: &[f64]) -> Vec<f64> {
+        let frame_size = self.config.frame_size;
+        let fft_size = 2 * frame_size;
+        let mut y_real: Vec<f64> = magnitude
+            .iter()
+            .zip(alter_phase.iter())
+            .map(|(m, p)| m * p.cos())
+            .collect();
+        let mut y_imag: Vec<f64> = magnitude
+            .iter()
+            .zip(alter_phase.iter())
+            .map(|(m, p)| m * p.sin())
+            .collect();
+
+        // IFFT
+        fft(&mut y_real, &mut y_imag, fft_size, true);
+
+        // Shift signal to lead
+        y_real.rotate_left(frame_size);
+
+        // Windowning real signal
+        for (y, w) in y_real.iter_mut().zip(self.analysis_window.iter()) {
+            *y *= w;
+        }
+
+        // Pitch-shift needs interpolation of audio signal.
+        if self.config.mode == Mode::PitchShift {
+            let interpolate_length = (fft_size as f64 * self.analysis_hopsize() as f64
+                / self.config.hop_size as f64) as usize;
+            interpolation(&fft_size, &interpolate_length, &y_real, &self.config.ratio)
+        } else {
+            y_real
+        }
+    }
+}
+
+/// Frequency direction derivatives (∆f,fwdφa) (·,n) and (∆f,backφa) (·,n) scaled to synthesis.
+pub(crate) fn frequency_delta_phi(phase: &[f64], scalling_factor: f64) -> (Vec<f64>, Vec<f64>) {
+    let fft_size = phase.len();
+    let mut forward = vec![0.0; fft_size];
+    let mut backward = vec![0.0; fft_size];
+
+    for j in 0..fft_size {
+        // Centerd value when it locates on the edge of frequency.
+        let frequency_delta_phi = if j + 1 >= fft_size || j as isize - 1 < 0 {
+            scalling_factor * principal_argument(phase[j])
+        } else {
+            scalling_factor / 2.0
+                * (principal_argument(phase[j] - phase[j - 1])
+                    + principal_argument(phase[j + 1] - phase[j]))
+        };
+
+        // (∆f,fwdφa) (m, n) = 1 / ba [φa(m + 1, n) − φa(m, n)] 2π
+        forward[j] = if j + 2 >= fft_size || j as isize - 1 < 0 {
+            frequency_delta_phi
+        } else {
+            scalling_factor / 2.0
+                * (principal_argument(phase[j] - phase[j - 1])
+                    + principal_argument(phase[j + 2] - phase[j + 1]))
+        };
+
+        // (∆f,backφa) (m, n) = 1 / ba [φa(m, n) − φa(m − 1, n)] 2π
+        backward[j] = if j + 1 >= fft_size || j as isize - 2 < 0 {
+            frequency_delta_phi
+        } else {
+            scalling_factor / 2.0
+                * (principal_argument(phase[j - 1] - phase[j - 2])
+                    + principal_argument(phase[j + 1] - phase[j]))
+        };
+    }
+
+    (forward, backward)
+}
+
+/// Inputs of the heap integration for the current frame.
+pub(crate) struct PhaseGradient<'a> {
+    pub magnitude: &'a [f64],
+    pub previous_magnitude: &'a [f64],
+    pub previous_phase: &'a [f64],
+    pub time_delta_phi: &'a [f64],
+    pub frequency_forward_delta_phi: &'a [f64],
+    pub frequency_backward_delta_phi: &'a [f64],
+}
+
+/// Phase gradient heap integration of the current `frame`.
+pub(crate) fn integrate_phase<R: Rng>(
+    gradient: &PhaseGradient,
+    frame: usize,
+    relative_tolerance: f64,
+    rng: &mut R,
+) -> Vec<f64> {
+    let fft_size = gradient.magnitude.len();
+    let mut alter_phase = vec![0.0; fft_size];
+    let mut max_heap: BinaryHeap<MaxHeap> = BinaryHeap::new();
+
+    // Preprocessing for heap sort.
+
+    // abstol ← tol·max(s(m,n) ∪ s(m,n - 1))
+    let absolute_tolerance = relative_tolerance
+        * f64::max(
+            gradient.magnitude.iter().cloned().fold(f64::NAN, f64::max),
+            gradient
+                .previous_magnitude
+                .iter()
+                .cloned()
+                .fold(f64::NAN, f64::max),
+        );
+    // set I = { m: s(m,n) > abstol }
+    let mut frequency_indices: Vec<bool> = gradient
+        .magnitude
+        .iter()
+        .map(|&x| x > absolute_tolerance)
+        .collect();
+    // Assign random values to φs(m,n) for m ∉ I
+    for (j, in_set) in frequency_indices.iter().enumerate() {
+        if !in_set {
+            alter_phase[j] = rng.gen();
+        }
+    }
+
+    // Construct a self-sorting max heap for (m,n) tuples
+    // Insert (m,n - 1) for m ∈ I into the heap
+    for (j, _) in frequency_indices.iter().enumerate().filter(|(_, &x)| x) {
+        max_heap.push(MaxHeap {
+            magnitude: gradient.previous_magnitude[j],
+            frequency_index: j,
+            frame: frame - 1,
+        });
+    }
+
+    // Remove (m,n) from I and insert (m,n) into the heap
+    let take = |frequency_indices: &mut Vec<bool>, max_heap: &mut BinaryHeap<MaxHeap>, j| {
+        frequency_indices[j] = false;
+        max_heap.push(MaxHeap {
+            magnitude: gradient.magnitude[j],
+            frequency_index: j,
+            frame,
+        });
+    };
+
+    while let Some(max) = max_heap.pop() {
+        let frequency_index = max.frequency_index;
+
+        // Propagate the phase in the time direction
+        if max.frame == frame - 1 {
+            // (mh,n) ∈ I
+            if frequency_indices[frequency_index] {
+                alter_phase[frequency_index] = gradient.previous_phase[frequency_index]
+                    + gradient.time_delta_phi[frequency_index];
+                take(&mut frequency_indices, &mut max_heap, frequency_index);
+            }
+        }
+
+        // Propagate the phase in the frequency direction
+        if max.frame == frame {
+            // The edge of frequency for forward
+            if frequency_index + 1 >= fft_size {
+                alter_phase[frequency_index] +=
+                    gradient.frequency_forward_delta_phi[frequency_index];
+                if frequency_indices[frequency_index] {
+                    take(&mut frequency_indices, &mut max_heap, frequency_index);
+                }
+                continue;
+            }
+
+            // The edge of frequency for backward
+            if frequency_index == 0 {
+                alter_phase[frequency_index] -=
+                    gradient.frequency_backward_delta_phi[frequency_index];
+                if frequency_indices[frequency_index] {
+                    take(&mut frequency_indices, &mut max_heap, frequency_index);
+                }
+                continue;
+            }
+
+            // (mh + 1,n) ∈ I
+            if frequency_indices[frequency_index + 1] {
+                alter_phase[frequency_index + 1] = alter_phase[frequency_index]
+                    + gradient.frequency_forward_delta_phi[frequency_index];
+                take(&mut frequency_indices, &mut max_heap, frequency_index + 1);
+            }
+
+            // (mh - 1,n) ∈ I
+            if frequency_indices[frequency_index - 1] {
+                alter_phase[frequency_index - 1] = alter_phase[frequency_index]
+                    - gradient.frequency_backward_delta_phi[frequency_index];
+                take(&mut frequency_indices, &mut max_heap, frequency_index - 1);
+            }
+        }
+    }
+
+    alter_phase
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reject_invalid_config() {
+        let config = VocoderConfig::new(Mode::TimeStretch, 1.0).with_frame_size(1000);
+        assert_eq!(
+            VocoderError::InvalidFrameSize(1000),
+            PhaseGradientVocoder::new(config).unwrap_err()
+        );
+
+        let config = VocoderConfig::new(Mode::TimeStretch, 0.0);
+        assert_eq!(
+            VocoderError::InvalidRatio(0.0),
+            PhaseGradientVocoder::new(config).unwrap_err()
+        );
+
+        // mid/side needs a pair of channels
+        let config = VocoderConfig {
+            link: ChannelLink::MidSide,
+            ..VocoderConfig::new(Mode::PitchShift, 1.2)
+        };
+        let vocoder = PhaseGradientVocoder::new(config).unwrap();
+        assert_eq!(
+            VocoderError::MidSideChannels(1),
+            vocoder.process(&[0.0; 100]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn output_length() {
+        let input: Vec<f64> = (0..8192).map(|i| (i as f64 * 0.05).sin() * 0.5).collect();
+
+        let config = VocoderConfig::new(Mode::TimeStretch, 1.5).with_frame_size(512);
+        let output = PhaseGradientVocoder::new(config)
+            .unwrap()
+            .process(&input)
+            .unwrap();
+        assert_eq!(12288, output.len());
+
+        let config = VocoderConfig::new(Mode::PitchShift, 1.5).with_frame_size(512);
+        let output = PhaseGradientVocoder::new(config)
+            .unwrap()
+            .process(&input)
+            .unwrap();
+        assert_eq!(8192, output.len());
+    }
+
+    #[test]
+    fn map_position_of_impulse() {
+        let mut input = vec![0.0; 16384];
+        for k in 0..64 {
+            input[6000 + k - 32] = (-((k as f64 - 32.0) / 8.0).powi(2)).exp();
+        }
+
+        for (mode, ratio) in [
+            (Mode::TimeStretch, 1.5),
+            (Mode::TimeStretch, 0.7),
+            (Mode::PitchShift, 1.3),
+        ] {
+            let config = VocoderConfig {
+                seed: Some(1),
+                ..VocoderConfig::new(mode, ratio).with_frame_size(1024)
+            };
+            let vocoder = PhaseGradientVocoder::new(config).unwrap();
+            let output = vocoder.process(&input).unwrap();
+            let energy =
+                |start: usize| output[start..start + 64].iter().map(|y| y * y).sum::<f64>();
+            let peak = (0..output.len() - 64)
+                .max_by(|a, b| energy(*a).total_cmp(&energy(*b)))
+                .unwrap()
+                + 32;
+            assert!(
+                peak.abs_diff(vocoder.map_position(6000)) < 64,
+                "{:?} {}",
+                mode,
+                peak
+            );
+        }
+    }
+}
@@ -1,144 +1,602 @@
-use std::{fs::File, io::prelude::Write};
+use std::{
+    fs::File,
+    io::{prelude::Write, BufWriter},
+};
 
-use crate::normalize::GenericNormalize;
+use crate::dither::Quantizer;
+use crate::error::{Error, Result};
+use crate::g711::Law;
+use crate::ima_adpcm::{self, ImaAdpcmFormat, BLOCK_ALIGN_PER_CHANNEL};
+use crate::metadata::{chunks_len, MetadataChunk};
+use crate::normalize::{Normalize, SampleFormat, I24};
+use crate::read::FormatExtensible;
+use crate::riff::{Ds64, RF64_SIZE};
+use crate::spec::{Codec, WaveSpec};
+use crate::writer::WaveWriter;
 
-pub fn normalize<T: GenericNormalize>(value: f64) -> Option<T> {
-    let max = T::MAX.to_f64()?;
-    let min = T::MIN.to_f64()?;
-    // 0.0 is median value between -1.0 and 1.0
-    let checked_value = if value.is_nan() {
-        let median = 0.0;
-        median
+fn encode<T: Normalize, const N: usize>(buffer: &[f64], to_le_bytes: fn(T) -> [u8; N]) -> Vec<u8> {
+    buffer
+        .iter()
+        .flat_map(|value| to_le_bytes(T::from_normalized(*value)))
+        .collect()
+}
+
+/// Quantize samples in the range from -1.0 to 1.0 into little-endian bytes.
+pub fn encode_samples(buffer: &[f64], sample_format: SampleFormat, bit: usize) -> Result<Vec<u8>> {
+    let bytes = match (sample_format, bit) {
+        (SampleFormat::Int, 8) => encode(buffer, u8::to_le_bytes),
+        (SampleFormat::Int, 16) => encode(buffer, i16::to_le_bytes),
+        (SampleFormat::Int, 24) => encode(buffer, I24::to_le_bytes),
+        (SampleFormat::Int, 32) => encode(buffer, i32::to_le_bytes),
+        (SampleFormat::Float, 32) => encode(buffer, f32::to_le_bytes),
+        (SampleFormat::Float, 64) => encode(buffer, f64::to_le_bytes),
+        _ => return Err(Error::UnsupportedBitsPerSample(bit, sample_format)),
+    };
+
+    Ok(bytes)
+}
+
+fn encode_dithered<T: Normalize, const N: usize>(
+    buffer: &[f64],
+    quantizer: &mut Quantizer,
+    to_le_bytes: fn(T) -> [u8; N],
+) -> Vec<u8> {
+    let max = T::MAX.to_f64().unwrap_or_default();
+    let min = T::MIN.to_f64().unwrap_or_default();
+    let channels = quantizer.channels();
+    buffer
+        .iter()
+        .enumerate()
+        .flat_map(|(i, value)| {
+            let checked_value = if value.is_nan() { 0.0 } else { *value };
+            // the same scale as `Normalize::from_normalized` before rounding
+            let scaled = checked_value * T::SCALE + T::ZERO;
+            let quantized = quantizer.quantize(scaled, i % channels, min, max);
+            to_le_bytes(T::from_scaled(quantized))
+        })
+        .collect()
+}
+
+/// Quantize interleaved samples with dither and noise shaping into little-endian bytes.
+/// IEEE float samples are not dithered.
+pub fn encode_samples_dithered(
+    buffer: &[f64],
+    sample_format: SampleFormat,
+    bit: usize,
+    quantizer: &mut Quantizer,
+) -> Result<Vec<u8>> {
+    let bytes = match (sample_format, bit) {
+        (SampleFormat::Int, 8) => encode_dithered(buffer, quantizer, u8::to_le_bytes),
+        (SampleFormat::Int, 16) => encode_dithered(buffer, quantizer, i16::to_le_bytes),
+        (SampleFormat::Int, 24) => encode_dithered(buffer, quantizer, I24::to_le_bytes),
+        (SampleFormat::Int, 32) => encode_dithered(buffer, quantizer, i32::to_le_bytes),
+        _ => return encode_samples(buffer, sample_format, bit),
+    };
+
+    Ok(bytes)
+}
+
+/// Join buffers of each channel into frames, shorter channels are padded with silence.
+pub fn interleave(buffer: &[Vec<f64>]) -> Vec<f64> {
+    let length = buffer
+        .iter()
+        .map(|channel| channel.len())
+        .max()
+        .unwrap_or(0);
+    let mut interleaved = Vec::with_capacity(length * buffer.len());
+    for i in 0..length {
+        for channel in buffer {
+            interleaved.push(channel.get(i).cloned().unwrap_or(0.0));
+        }
+    }
+
+    interleaved
+}
+
+/// Speaker positions of the first `channels` ones, or front center for monaural.
+fn default_channel_mask(channels: usize) -> usize {
+    if channels == 1 {
+        0x4
     } else {
-        value
+        (1 << channels.min(18)) - 1
+    }
+}
+
+/// Size of RIFF chunk of a file which has the header of `head_size` bytes and `size` bytes of
+/// samples with the pad byte.
+pub fn riff_chunk_size(head_size: usize, size: usize) -> usize {
+    head_size - 8 + size + size % 2
+}
+
+/// Bytes of `ds64` chunk without the table, which is reserved by `JUNK` chunk in RIFF.
+const DS64_CHUNK_SIZE: usize = 36;
+
+/// RIFF header followed by `JUNK` chunk which reserves `ds64` chunk, or RF64 header followed by
+/// `ds64` chunk if the sizes do not fit in 32 bits. Whether it is RF64 is returned with it.
+fn riff_header(riff_size: usize, data_size: usize, sample_count: usize) -> (Vec<u8>, bool) {
+    let rf64 = riff_size > u32::MAX as usize || data_size > u32::MAX as usize;
+    let mut head = Vec::with_capacity(12 + DS64_CHUNK_SIZE);
+    if rf64 {
+        head.extend_from_slice(b"RF64");
+        head.extend_from_slice(&RF64_SIZE.to_le_bytes());
+        head.extend_from_slice(b"WAVE");
+        head.extend_from_slice(b"ds64");
+        head.extend_from_slice(&(DS64_CHUNK_SIZE as u32 - 8).to_le_bytes());
+        let ds64 = Ds64 {
+            riff_size: riff_size as u64,
+            data_size: data_size as u64,
+            sample_count: sample_count as u64,
+            table: Vec::new(),
+        };
+        head.extend_from_slice(&ds64.to_bytes());
+    } else {
+        head.extend_from_slice(b"RIFF");
+        head.extend_from_slice(&(riff_size as u32).to_le_bytes());
+        head.extend_from_slice(b"WAVE");
+        head.extend_from_slice(b"JUNK");
+        head.extend_from_slice(&(DS64_CHUNK_SIZE as u32 - 8).to_le_bytes());
+        head.extend_from_slice(&[0; DS64_CHUNK_SIZE - 8]);
+    }
+
+    (head, rf64)
+}
+
+/// Size field of 32 bits, which is given by `ds64` chunk in RF64.
+fn size_field(size: usize, rf64: bool) -> u32 {
+    if rf64 {
+        RF64_SIZE
+    } else {
+        size as u32
+    }
+}
+
+/// RIFF or RF64 header, `fmt ` chunk and header of `data` chunk of `size` bytes,
+/// which are followed by chunks of `trailing_size` bytes.
+/// WAVE_FORMAT_EXTENSIBLE is used for more than two channels or more than 16 bits.
+pub fn wave_header(spec: &WaveSpec, size: usize, trailing_size: usize) -> Vec<u8> {
+    let extensible = if spec.needs_extensible() {
+        Some(FormatExtensible::new(
+            spec.codec(),
+            spec.bits_per_sample,
+            default_channel_mask(spec.channels),
+        ))
+    } else {
+        None
     };
-    let normalized = (checked_value - (-1.0)) / (1.0 - (-1.0)) * (max - min) + min;
+    let format_data_size = if extensible.is_some() { 40 } else { 16 };
+    let head_size = 28 + DS64_CHUNK_SIZE + format_data_size;
+    // RIFF header and `ds64` or `JUNK` chunk
+    let (mut head, rf64) = riff_header(
+        riff_chunk_size(head_size + trailing_size, size),
+        size,
+        size / spec.block_align().max(1),
+    );
 
-    let wrapping = if normalized > T::MAX.to_f64()? {
-        T::MAX.to_f64()?
-    } else if normalized < T::MIN.to_f64()? {
-        T::MIN.to_f64()?
+    // Chunk ID
+    head.extend_from_slice(b"fmt ");
+    // Chunk Data Size
+    head.extend_from_slice(&(format_data_size as u32).to_le_bytes());
+    // Compression Code
+    let format_tag = if extensible.is_some() {
+        Codec::Extensible
     } else {
-        normalized
+        spec.codec()
     };
+    head.extend_from_slice(&format_tag.code().to_le_bytes());
+    // Number of channels
+    head.extend_from_slice(&(spec.channels as u16).to_le_bytes());
+    // Sample rate
+    head.extend_from_slice(&(spec.sample_rate as u32).to_le_bytes());
+    // Average bytes per second
+    head.extend_from_slice(&(spec.bytes_per_second() as u32).to_le_bytes());
+    // Block align
+    head.extend_from_slice(&(spec.block_align() as u16).to_le_bytes());
+    // Significant bits per sample
+    head.extend_from_slice(&(spec.bits_per_sample as u16).to_le_bytes());
+    if let Some(extensible) = extensible {
+        // Size of extension
+        head.extend_from_slice(&22_u16.to_le_bytes());
+        // Valid bits per sample, channel mask and sub format
+        head.extend_from_slice(&extensible.to_bytes());
+    }
+
+    // Chunk ID
+    head.extend_from_slice(b"data");
+    // chunk size
+    head.extend_from_slice(&size_field(size, rf64).to_le_bytes());
 
-    Some(T::from_f64(wrapping)?)
+    head
 }
 
-pub fn wav_write(
-    filename: &str,
-    buffer: Vec<f64>,
-    size: usize,
+/// Header of a stream of unknown length like stdout, whose sizes of RIFF and `data` chunk are 0xFFFFFFFF.
+pub fn stream_wave_header(spec: &WaveSpec) -> Vec<u8> {
+    let mut head = wave_header(spec, 0, 0);
+    let data_size = head.len() - 4;
+    head[4..8].copy_from_slice(&RF64_SIZE.to_le_bytes());
+    head[data_size..].copy_from_slice(&RF64_SIZE.to_le_bytes());
+
+    head
+}
+
+/// Write samples of each channel in the format of `spec`.
+pub fn wav_write(filename: &str, buffer: Vec<Vec<f64>>, spec: &WaveSpec) -> Result<()> {
+    let mut writer = WaveWriter::new(BufWriter::new(File::create(filename)?), *spec)?;
+    writer.write_frames(&buffer)?;
+    writer.finalize()
+}
+
+/// Fields of `fmt ` chunk for compressed samples.
+struct CompressedFormat<'a> {
+    codec: Codec,
+    channels: usize,
     fs: usize,
+    bytes_per_second: usize,
+    block_align: usize,
     bit: usize,
-) -> std::io::Result<()> {
-    let mut head: Vec<u8> = vec![0; 44];
-
-    // Chunk ID
-    let riff_to_bytes = b"RIFF";
-    head[0] = riff_to_bytes[0];
-    head[1] = riff_to_bytes[1];
-    head[2] = riff_to_bytes[2];
-    head[3] = riff_to_bytes[3];
+    /// fields following the size of extension
+    extra: &'a [u8],
+}
 
-    let filesize = head.len() + size;
-    // Chunk Data Size
-    head[4] = ((filesize - 8).rotate_right(0) & 0xff) as u8;
-    head[5] = ((filesize - 8).rotate_right(8) & 0xff) as u8;
-    head[6] = ((filesize - 8).rotate_right(16) & 0xff) as u8;
-    head[7] = ((filesize - 8).rotate_right(24) & 0xff) as u8;
-
-    // RIFF Type
-    let wave_to_bytes = b"WAVE";
-    head[8] = wave_to_bytes[0];
-    head[9] = wave_to_bytes[1];
-    head[10] = wave_to_bytes[2];
-    head[11] = wave_to_bytes[3];
+/// RIFF or RF64 header, `fmt ` chunk, `fact` chunk and header of `data` chunk.
+/// `metadata` follows `data` chunk.
+fn compressed_wave_header(
+    format: &CompressedFormat,
+    sample_frames: usize,
+    size: usize,
+    metadata: &[MetadataChunk],
+) -> Vec<u8> {
+    let format_data_size = 18 + format.extra.len();
+    let head_size = 40 + DS64_CHUNK_SIZE + format_data_size;
+    // RIFF header and `ds64` or `JUNK` chunk
+    let (mut head, rf64) = riff_header(
+        riff_chunk_size(head_size + chunks_len(metadata), size),
+        size,
+        sample_frames,
+    );
 
     // Chunk ID
-    let fmt_to_bytes = b"fmt ";
-    head[12] = fmt_to_bytes[0];
-    head[13] = fmt_to_bytes[1];
-    head[14] = fmt_to_bytes[2];
-    head[15] = fmt_to_bytes[3];
-
+    head.extend_from_slice(b"fmt ");
     // Chunk Data Size
-    head[16] = 16;
-    head[17] = 0;
-    head[18] = 0;
-    head[19] = 0;
-
+    head.extend_from_slice(&(format_data_size as u32).to_le_bytes());
     // Compression Code
-    head[20] = 1;
-    head[21] = 0;
-
+    head.extend_from_slice(&format.codec.code().to_le_bytes());
     // Number of channels
-    head[22] = 1;
-    head[23] = 0;
-
+    head.extend_from_slice(&(format.channels as u16).to_le_bytes());
     // Sample rate
-    head[24] = (fs.rotate_right(0) & 0xff) as u8;
-    head[25] = (fs.rotate_right(8) & 0xff) as u8;
-    head[26] = (fs.rotate_right(16) & 0xff) as u8;
-    head[27] = (fs.rotate_right(24) & 0xff) as u8;
-
+    head.extend_from_slice(&(format.fs as u32).to_le_bytes());
     // Average bytes per second
-    head[28] = ((fs * (bit / 8)).rotate_right(0) & 0xff) as u8;
-    head[29] = ((fs * (bit / 8)).rotate_right(8) & 0xff) as u8;
-    head[30] = ((fs * (bit / 8)).rotate_right(16) & 0xff) as u8;
-    head[31] = ((fs * (bit / 8)).rotate_right(24) & 0xff) as u8;
-
+    head.extend_from_slice(&(format.bytes_per_second as u32).to_le_bytes());
     // Block align
-    head[32] = ((bit / 8).rotate_right(0) & 0xff) as u8;
-    head[33] = ((bit / 8).rotate_right(8) & 0xff) as u8;
-
+    head.extend_from_slice(&(format.block_align as u16).to_le_bytes());
     // Significant bits per sample
-    head[34] = (bit.rotate_right(0) & 0xff) as u8;
-    head[35] = (bit.rotate_right(8) & 0xff) as u8;
+    head.extend_from_slice(&(format.bit as u16).to_le_bytes());
+    // Size of extension
+    head.extend_from_slice(&(format.extra.len() as u16).to_le_bytes());
+    head.extend_from_slice(format.extra);
 
     // Chunk ID
-    let data_to_bytes = b"data";
-    head[36] = data_to_bytes[0];
-    head[37] = data_to_bytes[1];
-    head[38] = data_to_bytes[2];
-    head[39] = data_to_bytes[3];
+    head.extend_from_slice(b"fact");
+    // Chunk Data Size
+    head.extend_from_slice(&4_u32.to_le_bytes());
+    // Number of sample frames
+    head.extend_from_slice(&size_field(sample_frames, rf64).to_le_bytes());
 
+    // Chunk ID
+    head.extend_from_slice(b"data");
     // chunk size
-    head[40] = (size.rotate_right(0) & 0xff) as u8;
-    head[41] = (size.rotate_right(8) & 0xff) as u8;
-    head[42] = (size.rotate_right(16) & 0xff) as u8;
-    head[43] = (size.rotate_right(24) & 0xff) as u8;
-
-    // write
-    let mut file = File::create(filename)?;
-    file.write_all(&head)?;
-    if bit == 8 {
-        for i in 0..buffer.len() {
-            let sample = normalize::<u8>(buffer[i]).unwrap_or(0);
-            let byte = sample.to_le_bytes();
-            file.write_all(&byte)?;
-        }
-    } else {
-        for i in 0..buffer.len() {
-            let sample = normalize::<i16>(buffer[i]).unwrap_or(0);
-            let byte = sample.to_le_bytes();
-            file.write_all(&byte)?;
-        }
-    };
+    head.extend_from_slice(&size_field(size, rf64).to_le_bytes());
+
+    head
+}
+
+fn write_file(filename: &str, head: &[u8], bytes: &[u8], metadata: &[MetadataChunk]) -> Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    file.write_all(head)?;
+    file.write_all(bytes)?;
+    // pad byte of `data` chunk
+    if bytes.len() % 2 == 1 {
+        file.write_all(&[0])?;
+    }
+    for chunk in metadata {
+        file.write_all(&chunk.to_bytes())?;
+    }
     file.flush()?;
 
     Ok(())
 }
 
+/// Quantize samples into 16-bit interleaved ones.
+fn interleave_i16(buffer: &[Vec<f64>]) -> Vec<i16> {
+    interleave(buffer)
+        .iter()
+        .map(|value| i16::from_normalized(*value))
+        .collect()
+}
+
+/// Write samples compressed with IMA ADPCM, which takes 4 bits per sample.
+/// `metadata` follows the samples.
+pub fn wav_write_ima_adpcm(
+    filename: &str,
+    buffer: Vec<Vec<f64>>,
+    fs: usize,
+    metadata: &[MetadataChunk],
+) -> Result<()> {
+    let channels = buffer.len();
+    let format = ImaAdpcmFormat::new(channels, channels * BLOCK_ALIGN_PER_CHANNEL);
+    let samples = interleave_i16(&buffer);
+    let bytes = ima_adpcm::encode(&samples, &format);
+    let head = compressed_wave_header(
+        &CompressedFormat {
+            codec: Codec::ImaAdpcm,
+            channels,
+            fs,
+            bytes_per_second: fs * format.block_align / format.samples_per_block,
+            block_align: format.block_align,
+            bit: 4,
+            // Samples per block
+            extra: &(format.samples_per_block as u16).to_le_bytes(),
+        },
+        samples.len() / channels.max(1),
+        bytes.len(),
+        metadata,
+    );
+
+    write_file(filename, &head, &bytes, metadata)
+}
+
+/// Write samples companded with G.711 A-law or mu-law, which takes 8 bits per sample.
+/// `metadata` follows the samples.
+pub fn wav_write_g711(
+    filename: &str,
+    buffer: Vec<Vec<f64>>,
+    fs: usize,
+    law: Law,
+    metadata: &[MetadataChunk],
+) -> Result<()> {
+    let channels = buffer.len();
+    let samples = interleave_i16(&buffer);
+    let bytes: Vec<u8> = samples.iter().map(|sample| law.encode(*sample)).collect();
+    let head = compressed_wave_header(
+        &CompressedFormat {
+            codec: law.codec(),
+            channels,
+            fs,
+            bytes_per_second: fs * channels,
+            block_align: channels,
+            bit: 8,
+            extra: &[],
+        },
+        samples.len() / channels.max(1),
+        bytes.len(),
+        metadata,
+    );
+
+    write_file(filename, &head, &bytes, metadata)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::spec::ChannelLayout;
+    use proptest::prelude::*;
     #[test]
     fn normalize_i16() {
-        let result: i16 = normalize(f64::NAN).unwrap_or(0);
+        let result = i16::from_normalized(f64::NAN);
         assert_eq!(0, result);
 
-        let result: u8 = normalize(f64::NAN).unwrap_or(0);
-        assert_eq!(127, result);
+        let result = u8::from_normalized(f64::NAN);
+        assert_eq!(128, result);
+    }
+
+    /// Decode little-endian samples and encode them again.
+    fn read_write(bytes: &[u8], sample_format: SampleFormat, bit: usize) -> Vec<u8> {
+        let samples = crate::read::decode_samples(bytes, sample_format, bit).unwrap();
+        encode_samples(&samples, sample_format, bit).unwrap()
+    }
+
+    // every code of 8, 16 and 24 bits survives normalization and quantization
+    #[test]
+    fn read_write_every_u8() {
+        let bytes: Vec<u8> = (u8::MIN..=u8::MAX).collect();
+        assert_eq!(bytes, read_write(&bytes, SampleFormat::Int, 8));
+    }
+
+    #[test]
+    fn read_write_every_i16() {
+        let bytes: Vec<u8> = (i16::MIN..=i16::MAX)
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        assert_eq!(bytes, read_write(&bytes, SampleFormat::Int, 16));
+    }
+
+    #[test]
+    fn read_write_every_i24() {
+        let (min, max) = (-(1 << 23), (1 << 23) - 1);
+        for start in (min..=max).step_by(1 << 16) {
+            let bytes: Vec<u8> = (start..(start + (1 << 16)).min(max + 1))
+                .flat_map(|value| I24::new(value).unwrap().to_le_bytes())
+                .collect();
+            assert_eq!(bytes, read_write(&bytes, SampleFormat::Int, 24));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn read_write_i32(value: i32) {
+            let bytes = value.to_le_bytes().to_vec();
+            prop_assert_eq!(&bytes, &read_write(&bytes, SampleFormat::Int, 32));
+        }
+
+        #[test]
+        fn read_write_f32(value in -1.0f32..=1.0) {
+            let bytes = value.to_le_bytes().to_vec();
+            prop_assert_eq!(&bytes, &read_write(&bytes, SampleFormat::Float, 32));
+        }
+
+        #[test]
+        fn read_write_f64(value in -1.0f64..=1.0) {
+            let bytes = value.to_le_bytes().to_vec();
+            prop_assert_eq!(&bytes, &read_write(&bytes, SampleFormat::Float, 64));
+        }
+
+        #[test]
+        fn read_write_without_dither(values: Vec<i16>) {
+            let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+            let samples = crate::read::decode_samples(&bytes, SampleFormat::Int, 16).unwrap();
+            let config = crate::dither::DitherConfig {
+                dither: crate::dither::Dither::None,
+                ..Default::default()
+            };
+            let mut quantizer = Quantizer::new(config, 1);
+            let result =
+                encode_samples_dithered(&samples, SampleFormat::Int, 16, &mut quantizer).unwrap();
+            prop_assert_eq!(bytes, result);
+        }
+    }
+
+    #[test]
+    fn write_read_stereo() {
+        let path = std::env::temp_dir().join("phase-gradient-vocoder-write-read-stereo.wav");
+        let filename = path.to_str().unwrap();
+        let left: Vec<f64> = (0..100).map(|i| i as f64 / 100.0).collect();
+        let right: Vec<f64> = (0..100).map(|i| -(i as f64) / 100.0).collect();
+
+        let spec = WaveSpec {
+            channels: 2,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 16,
+        };
+
+        wav_write(filename, vec![left.clone(), right.clone()], &spec).unwrap();
+        let wave = crate::read::wav_read(filename).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(spec, wave.spec());
+        assert_eq!(ChannelLayout::Stereo, wave.channels);
+        assert_eq!(Codec::Pcm, wave.compression_code);
+        assert!(wave.extensible.is_none());
+        assert_eq!(4, wave.block_align);
+        assert_eq!(32000, wave.bytes_per_second);
+        for (expected, channel) in [left, right].iter().zip(wave.normalized_sample_data.iter()) {
+            assert_eq!(expected.len(), channel.len());
+            for (a, b) in expected.iter().zip(channel.iter()) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn write_read_formats() {
+        let path = std::env::temp_dir().join("phase-gradient-vocoder-write-read-formats.wav");
+        let filename = path.to_str().unwrap();
+        let buffer: Vec<f64> = (0..100).map(|i| (i as f64 * 0.1).sin() * 0.9).collect();
+
+        for (sample_format, bit, tolerance) in [
+            (SampleFormat::Int, 24, 1e-6),
+            (SampleFormat::Int, 32, 1e-9),
+            (SampleFormat::Float, 32, 1e-7),
+            (SampleFormat::Float, 64, 1e-15),
+        ] {
+            let spec = WaveSpec {
+                channels: 1,
+                sample_rate: 8000,
+                sample_format,
+                bits_per_sample: bit,
+            };
+            wav_write(filename, vec![buffer.clone()], &spec).unwrap();
+            let wave = crate::read::wav_read(filename).unwrap();
+
+            assert_eq!(spec, wave.spec());
+            assert_eq!(bit > 16, wave.extensible.is_some());
+            assert_eq!(bit, wave.bits_per_sample);
+            for (a, b) in buffer.iter().zip(wave.normalized_sample_data[0].iter()) {
+                assert!((a - b).abs() < tolerance);
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn write_read_extensible_channels() {
+        let path = std::env::temp_dir().join("phase-gradient-vocoder-write-read-extensible.wav");
+        let filename = path.to_str().unwrap();
+        let buffer: Vec<Vec<f64>> = (0..6).map(|c| vec![c as f64 / 8.0; 10]).collect();
+
+        let spec = WaveSpec {
+            channels: 6,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 16,
+        };
+        assert!(matches!(
+            wav_write(filename, buffer[0..2].to_vec(), &spec),
+            Err(Error::ChannelMismatch {
+                expected: 6,
+                found: 2
+            })
+        ));
+        wav_write(filename, buffer.clone(), &spec).unwrap();
+        let wave = crate::read::wav_read(filename).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let extensible = wave.extensible.unwrap();
+        assert_eq!(Some(Codec::Pcm), extensible.sub_format_codec());
+        assert_eq!(ChannelLayout::Multichannel(6), wave.channels);
+        assert_eq!(0x3F, extensible.channel_mask);
+        assert_eq!(16, extensible.valid_bits_per_sample);
+        assert_eq!(6, wave.normalized_sample_data.len());
+        for (expected, channel) in buffer.iter().zip(wave.normalized_sample_data.iter()) {
+            assert!((expected[0] - channel[0]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn write_read_ima_adpcm() {
+        let path = std::env::temp_dir().join("phase-gradient-vocoder-write-read-ima-adpcm.wav");
+        let filename = path.to_str().unwrap();
+        let buffer: Vec<f64> = (0..2000).map(|i| (i as f64 * 0.05).sin() * 0.5).collect();
+
+        let metadata = vec![MetadataChunk::new(*b"bext", vec![1; 603])];
+        let channels = vec![buffer.clone(), buffer.clone()];
+        wav_write_ima_adpcm(filename, channels, 8000, &metadata).unwrap();
+        let wave = crate::read::wav_read(filename).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(metadata, wave.metadata);
+
+        assert_eq!(Codec::ImaAdpcm, wave.compression_code);
+        assert_eq!(4, wave.bits_per_sample);
+        assert_eq!(16, wave.pcm_bits_per_sample);
+        assert_eq!(1024, wave.block_align);
+        for channel in wave.normalized_sample_data.iter() {
+            assert_eq!(buffer.len(), channel.len());
+            // after the step size adapts
+            for (a, b) in buffer.iter().zip(channel.iter()).skip(32) {
+                assert!((a - b).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn write_read_g711() {
+        let path = std::env::temp_dir().join("phase-gradient-vocoder-write-read-g711.wav");
+        let filename = path.to_str().unwrap();
+        let buffer: Vec<f64> = (0..101).map(|i| (i as f64 * 0.05).sin() * 0.5).collect();
+
+        for law in [Law::ALaw, Law::MuLaw] {
+            wav_write_g711(filename, vec![buffer.clone()], 8000, law, &[]).unwrap();
+            let wave = crate::read::wav_read(filename).unwrap();
+
+            assert_eq!(law.codec(), wave.compression_code);
+            assert_eq!(Some(law), wave.companding_law);
+            assert_eq!(8, wave.bits_per_sample);
+            assert_eq!(16, wave.pcm_bits_per_sample);
+            assert_eq!(buffer.len(), wave.normalized_sample_data[0].len());
+            for (a, b) in buffer.iter().zip(wave.normalized_sample_data[0].iter()) {
+                assert!((a - b).abs() < 0.02);
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,279 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::dither::{DitherConfig, Quantizer};
+use crate::error::{Error, Result};
+use crate::metadata::{chunks_len, MetadataChunk};
+use crate::spec::WaveSpec;
+use crate::write::{encode_samples, encode_samples_dithered, interleave, wave_header};
+
+/// Writer which accepts samples block by block and patches sizes in the header at last.
+/// The header becomes RF64 if the file exceeds 4 GiB, its `ds64` chunk is reserved by `JUNK` chunk.
+#[derive(Debug)]
+pub struct WaveWriter<W: Write + Seek> {
+    writer: W,
+    spec: WaveSpec,
+    /// bytes of the header
+    head_size: usize,
+    /// bytes of samples written so far
+    size: usize,
+    /// quantizer with dither, samples are just scaled if it is not given
+    quantizer: Option<Quantizer>,
+    /// chunks written after `data` chunk
+    metadata: Vec<MetadataChunk>,
+    finalized: bool,
+}
+
+impl<W: Write + Seek> WaveWriter<W> {
+    /// Write the header whose sizes are patched by `finalize`.
+    pub fn new(mut writer: W, spec: WaveSpec) -> Result<Self> {
+        let head = wave_header(&spec, 0, 0);
+        writer.write_all(&head)?;
+
+        Ok(WaveWriter {
+            writer,
+            spec,
+            head_size: head.len(),
+            size: 0,
+            quantizer: None,
+            metadata: Vec::new(),
+            finalized: false,
+        })
+    }
+
+    /// Writer which quantizes samples with dither and noise shaping.
+    pub fn with_dither(mut self, config: DitherConfig) -> Self {
+        self.quantizer = Some(Quantizer::new(config, self.spec.channels));
+        self
+    }
+
+    /// Writer which writes `metadata` after the samples, so that it need not be known in advance.
+    pub fn with_metadata(mut self, metadata: Vec<MetadataChunk>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn spec(&self) -> &WaveSpec {
+        &self.spec
+    }
+
+    /// Number of frames written so far.
+    pub fn len(&self) -> usize {
+        self.size / self.spec.block_align().max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Write samples of each channel, shorter channels are padded with silence.
+    pub fn write_frames<T: AsRef<[f64]>>(&mut self, buffer: &[T]) -> Result<()> {
+        if buffer.len() != self.spec.channels {
+            return Err(Error::ChannelMismatch {
+                expected: self.spec.channels,
+                found: buffer.len(),
+            });
+        }
+        let buffer: Vec<Vec<f64>> = buffer.iter().map(|c| c.as_ref().to_vec()).collect();
+        let interleaved = interleave(&buffer);
+        let bytes = match self.quantizer.as_mut() {
+            Some(quantizer) => encode_samples_dithered(
+                &interleaved,
+                self.spec.sample_format,
+                self.spec.bits_per_sample,
+                quantizer,
+            )?,
+            None => encode_samples(
+                &interleaved,
+                self.spec.sample_format,
+                self.spec.bits_per_sample,
+            )?,
+        };
+        self.writer.write_all(&bytes)?;
+        self.size += bytes.len();
+
+        Ok(())
+    }
+
+    /// Write the pad byte and metadata, and rewrite the header of the same size with the sizes.
+    fn patch_header(&mut self) -> Result<()> {
+        if self.size % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        for chunk in self.metadata.iter() {
+            self.writer.write_all(&chunk.to_bytes())?;
+        }
+        let head = wave_header(&self.spec, self.size, chunks_len(&self.metadata));
+        debug_assert_eq!(self.head_size, head.len());
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&head)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Complete the file, which is also done on drop ignoring errors.
+    pub fn finalize(mut self) -> Result<()> {
+        self.finalized = true;
+        self.patch_header()
+    }
+}
+
+impl<W: Write + Seek> Drop for WaveWriter<W> {
+    fn drop(&mut self) {
+        if !self.finalized {
+            let _ = self.patch_header();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize::SampleFormat;
+    use crate::read::parse_wave;
+    use crate::riff::RF64_SIZE;
+    use std::io::Cursor;
+
+    #[test]
+    fn patch_sizes_of_blocks() {
+        let spec = WaveSpec {
+            channels: 1,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 24,
+        };
+        let buffer: Vec<f64> = (0..301).map(|i| (i as f64 * 0.1).sin() * 0.5).collect();
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut writer = WaveWriter::new(&mut buf, spec).unwrap();
+        for block in buffer.chunks(100) {
+            writer.write_frames(&[block]).unwrap();
+        }
+        assert_eq!(301, writer.len());
+        assert!(writer.write_frames(&[&buffer, &buffer]).is_err());
+        writer.finalize().unwrap();
+
+        let buf = buf.into_inner();
+        // odd size of `data` chunk is followed by the pad byte
+        assert_eq!(0, buf.len() % 2);
+        let wave = parse_wave(&buf).unwrap();
+        assert_eq!(buf.len(), wave.file_size);
+        assert_eq!(301 * 3, wave.chunk_data_size);
+        assert_eq!(spec, wave.spec());
+        for (a, b) in buffer.iter().zip(wave.normalized_sample_data[0].iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn finalize_on_drop() {
+        let spec = WaveSpec {
+            channels: 1,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Float,
+            bits_per_sample: 32,
+        };
+        let mut buf = Cursor::new(Vec::new());
+        {
+            let mut writer = WaveWriter::new(&mut buf, spec).unwrap();
+            writer.write_frames(&[vec![0.5; 10]]).unwrap();
+        }
+
+        let wave = parse_wave(buf.get_ref()).unwrap();
+        assert_eq!(vec![0.5; 10], wave.normalized_sample_data[0]);
+    }
+
+    #[test]
+    fn dither_with_seed() {
+        use crate::dither::NoiseShaping;
+
+        let spec = WaveSpec {
+            channels: 2,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 8,
+        };
+        let buffer: Vec<f64> = (0..1000).map(|i| (i as f64 * 0.01).sin() * 0.1).collect();
+        let write = |seed| {
+            let mut buf = Cursor::new(Vec::new());
+            let mut writer = WaveWriter::new(&mut buf, spec)
+                .unwrap()
+                .with_dither(DitherConfig {
+                    noise_shaping: NoiseShaping::SecondOrder,
+                    seed,
+                    ..Default::default()
+                });
+            for block in buffer.chunks(300) {
+                writer.write_frames(&[block, block]).unwrap();
+            }
+            writer.finalize().unwrap();
+            buf.into_inner()
+        };
+
+        let wave = write(1);
+        assert_eq!(wave, write(1));
+        assert_ne!(wave, write(2));
+        let wave = parse_wave(&wave).unwrap();
+        for channel in wave.normalized_sample_data {
+            assert_eq!(1000, channel.len());
+            for (a, b) in buffer.iter().zip(channel) {
+                // a few LSB of 8 bits
+                assert!((a - b).abs() < 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn write_metadata_after_samples() {
+        let spec = WaveSpec {
+            channels: 1,
+            sample_rate: 8000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 8,
+        };
+        let metadata = vec![
+            MetadataChunk::new(*b"LIST", b"INFOICMT\x03\x00\x00\x00ab\x00".to_vec()),
+            MetadataChunk::new(*b"iXML", b"<BWFXML/>".to_vec()),
+        ];
+        let mut buf = Cursor::new(Vec::new());
+        let mut writer = WaveWriter::new(&mut buf, spec)
+            .unwrap()
+            .with_metadata(metadata.clone());
+        writer.write_frames(&[vec![0.25; 11]]).unwrap();
+        writer.finalize().unwrap();
+
+        let buf = buf.into_inner();
+        let wave = parse_wave(&buf).unwrap();
+        assert_eq!(buf.len(), wave.file_size);
+        assert_eq!(11, wave.normalized_sample_data[0].len());
+        assert_eq!(metadata, wave.metadata);
+    }
+
+    #[test]
+    fn switch_to_rf64_over_4_gib() {
+        use crate::riff::rf64_ds64;
+
+        let spec = WaveSpec {
+            channels: 6,
+            sample_rate: 48000,
+            sample_format: SampleFormat::Int,
+            bits_per_sample: 24,
+        };
+        let riff = wave_header(&spec, 1000 * 18, 0);
+        assert_eq!(b"RIFF", &riff[0..4]);
+        assert_eq!(b"JUNK", &riff[12..16]);
+        assert_eq!(None, rf64_ds64(&riff));
+
+        // the header has the same size, so that the writer can patch it in place
+        let size = 300_000_000 * 18;
+        let rf64 = wave_header(&spec, size, 11);
+        assert_eq!(riff.len(), rf64.len());
+        assert_eq!(b"RF64\xff\xff\xff\xffWAVEds64", &rf64[0..16]);
+        let ds64 = rf64_ds64(&rf64).unwrap();
+        assert_eq!(rf64.len() as u64 - 8 + size as u64 + 11, ds64.riff_size);
+        assert_eq!(size as u64, ds64.data_size);
+        assert_eq!(300_000_000, ds64.sample_count);
+        assert_eq!(&RF64_SIZE.to_le_bytes(), &rf64[rf64.len() - 4..]);
+    }
+}